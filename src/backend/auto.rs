@@ -0,0 +1,470 @@
+use std::{env, io, os::fd::RawFd, path::PathBuf, str::FromStr};
+
+use crate::{
+    config::Config,
+    generator::GeneratorOptions,
+    hardening::{self, SandboxProfile, Syscalls},
+};
+
+use super::{
+    gtk::{GtkBackend, Presentation},
+    layer_shell::LayerShellOptions,
+    scripted::ScriptedBackend,
+    tty::TtyBackend,
+    BackendError, ConfirmOutcome, DialogOptions, MessageOutcome, PinOutcome, PinentryBackend,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    LayerShell,
+    Gtk,
+    Tty,
+    /// Never picked automatically, it has to be asked for.
+    Scripted,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "layer-shell" => Ok(BackendKind::LayerShell),
+            "gtk" => Ok(BackendKind::Gtk),
+            "tty" => Ok(BackendKind::Tty),
+            "scripted" => Ok(BackendKind::Scripted),
+            _ => Err(format!("unknown backend `{}`", input)),
+        }
+    }
+}
+
+/// What GTK still reads and writes once it is initialized. The home directory
+/// itself is left out, along with the keys in it.
+fn graphical_profile() -> SandboxProfile {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let xdg_home = |name, fallback| {
+        env::var_os(name)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|home| home.join(fallback)))
+    };
+    let xdg_dirs = |name| {
+        env::var_os(name)
+            .map(|dirs| env::split_paths(&dirs).collect())
+            .unwrap_or_else(Vec::new)
+    };
+
+    // themes, icons, fonts, settings and GPU drivers
+    let mut readable: Vec<PathBuf> = [
+        "/usr",
+        "/etc",
+        "/opt",
+        "/nix/store",
+        "/proc",
+        "/sys",
+        "/run",
+        "/var/cache/fontconfig",
+    ]
+    .map(PathBuf::from)
+    .into();
+    readable.extend(xdg_dirs("XDG_DATA_DIRS"));
+    readable.extend(xdg_dirs("XDG_CONFIG_DIRS"));
+    readable.extend(xdg_home("XDG_DATA_HOME", ".local/share"));
+    readable.extend(xdg_home("XDG_CONFIG_HOME", ".config"));
+    if let Some(home) = &home {
+        readable.extend([".icons", ".themes", ".fonts"].map(|dir| home.join(dir)));
+    }
+
+    // GPU render nodes, the fontconfig and shader caches, and the runtime
+    // directory for buffers shared with the compositor
+    let writable = [
+        Some(PathBuf::from("/dev")),
+        xdg_home("XDG_CACHE_HOME", ".cache"),
+        env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    SandboxProfile {
+        readable,
+        writable,
+        syscalls: Syscalls::Graphical,
+    }
+}
+
+/// The displays this process can reach without help from the client.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Displays {
+    pub wayland: bool,
+    pub x11: bool,
+}
+
+impl Displays {
+    pub fn from_env() -> Self {
+        let is_set = |name| env::var_os(name).is_some_and(|value| !value.is_empty());
+        Displays {
+            wayland: is_set("WAYLAND_DISPLAY"),
+            x11: is_set("DISPLAY"),
+        }
+    }
+}
+
+/// Picks a backend for every dialog from the displays and the options the
+/// client has sent so far, moving on to the next one if a backend cannot be
+/// shown.
+///
+/// With the sandbox turned on, it is applied once the first backend has been
+/// prepared for a dialog, and that backend is the only one tried from then on.
+pub struct AutoBackend {
+    forced: Option<BackendKind>,
+    sandbox: bool,
+    /// The backend the sandbox was applied for.
+    sandboxed: Option<BackendKind>,
+    displays: Displays,
+    layer_shell: LayerShellOptions,
+    scripted: ScriptedBackend,
+    generator: GeneratorOptions,
+    client: Option<RawFd>,
+}
+
+impl AutoBackend {
+    /// A backend set in `config` is the only one that is tried.
+    pub fn new(config: Config, displays: Displays) -> Self {
+        AutoBackend {
+            forced: config.backend,
+            sandbox: config.hardening.sandbox,
+            sandboxed: None,
+            displays,
+            layer_shell: config.layer_shell,
+            scripted: ScriptedBackend::new(config.scripted),
+            generator: config.generator,
+            client: None,
+        }
+    }
+
+    /// The backends to try for a dialog with `options`, best first.
+    fn candidates(&self, options: &DialogOptions) -> Vec<BackendKind> {
+        if let Some(forced) = self.sandboxed.or(self.forced) {
+            return vec![forced];
+        }
+
+        let mut candidates = vec![];
+        if self.displays.wayland {
+            candidates.push(BackendKind::LayerShell);
+        }
+        if self.displays.wayland || self.displays.x11 || options.display.is_some() {
+            candidates.push(BackendKind::Gtk);
+        }
+        if options.tty_name.is_some() {
+            candidates.push(BackendKind::Tty);
+        }
+        candidates
+    }
+
+    /// Like the other pinentries, a display from the client is only used when
+    /// we were started without one.
+    fn client_display(&self, options: &DialogOptions) -> Option<String> {
+        let started_without = !self.displays.wayland && !self.displays.x11;
+        options.display.clone().filter(|_| started_without)
+    }
+
+    fn backend(&self, kind: BackendKind, options: &DialogOptions) -> Box<dyn PinentryBackend> {
+        match kind {
+            BackendKind::LayerShell => Box::new(GtkBackend {
+                presentation: Presentation::Overlay(self.layer_shell.clone()),
+                generator: self.generator.clone(),
+                client: self.client,
+                display: None,
+            }),
+            BackendKind::Gtk => Box::new(GtkBackend {
+                presentation: Presentation::Window,
+                generator: self.generator.clone(),
+                client: self.client,
+                display: self.client_display(options),
+            }),
+            BackendKind::Tty => Box::new(TtyBackend {
+                client: self.client,
+            }),
+            BackendKind::Scripted => unreachable!("the scripted backend is kept around"),
+        }
+    }
+
+    /// The files and syscalls `kind` needs once it has been prepared.
+    fn sandbox_profile(&self, kind: BackendKind) -> SandboxProfile {
+        match kind {
+            BackendKind::LayerShell | BackendKind::Gtk => graphical_profile(),
+            BackendKind::Tty => SandboxProfile {
+                writable: vec![PathBuf::from("/dev")],
+                ..Default::default()
+            },
+            BackendKind::Scripted => SandboxProfile {
+                readable: self.scripted.readable_paths(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Applies the sandbox for `kind` if it was asked for and is not in place
+    /// yet. No dialog is shown without it.
+    fn enter_sandbox(&mut self, kind: BackendKind) -> Result<(), BackendError> {
+        if !self.sandbox || self.sandboxed.is_some() {
+            return Ok(());
+        }
+
+        hardening::sandbox(&self.sandbox_profile(kind)).map_err(|error| {
+            BackendError::Io(io::Error::new(error.kind(), format!("sandbox: {}", error)))
+        })?;
+        self.sandboxed = Some(kind);
+        Ok(())
+    }
+
+    /// Calls `show` with each candidate backend until one of them could show
+    /// the dialog.
+    fn show<T>(
+        &mut self,
+        options: &DialogOptions,
+        mut show: impl FnMut(&mut dyn PinentryBackend) -> Result<T, BackendError>,
+    ) -> Result<T, BackendError> {
+        let mut reasons = vec![];
+        for kind in self.candidates(options) {
+            let result = match kind {
+                BackendKind::Scripted => self
+                    .enter_sandbox(kind)
+                    .and_then(|()| show(&mut self.scripted)),
+                kind => {
+                    let mut backend = self.backend(kind, options);
+                    backend
+                        .prepare(options)
+                        .and_then(|()| self.enter_sandbox(kind))
+                        .and_then(|()| show(backend.as_mut()))
+                }
+            };
+            match result {
+                Err(BackendError::Unavailable(reason)) => reasons.push(reason),
+                result => return result,
+            }
+        }
+
+        if reasons.is_empty() {
+            reasons.push("no display or terminal".to_string());
+        }
+        Err(BackendError::Unavailable(reasons.join(", ")))
+    }
+}
+
+impl PinentryBackend for AutoBackend {
+    fn get_pin(
+        &mut self,
+        options: &DialogOptions,
+        quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+        check_pin: &mut dyn FnMut(&str) -> io::Result<Option<String>>,
+    ) -> Result<PinOutcome, BackendError> {
+        self.show(options, |backend| {
+            backend.get_pin(options, quality, check_pin)
+        })
+    }
+
+    fn confirm(&mut self, options: &DialogOptions) -> Result<ConfirmOutcome, BackendError> {
+        self.show(options, |backend| backend.confirm(options))
+    }
+
+    fn message(&mut self, options: &DialogOptions) -> Result<MessageOutcome, BackendError> {
+        self.show(options, |backend| backend.message(options))
+    }
+
+    /// The flavor of the backend tried first, or of a GTK window if there is
+    /// nothing to try.
+    fn flavor(&self, options: &DialogOptions) -> &'static str {
+        let kind = self
+            .candidates(options)
+            .first()
+            .copied()
+            .unwrap_or(BackendKind::Gtk);
+        match kind {
+            BackendKind::Scripted => self.scripted.flavor(options),
+            kind => self.backend(kind, options).flavor(options),
+        }
+    }
+
+    fn watch_client(&mut self, fd: RawFd) {
+        self.client = Some(fd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::scripted::PinSource, *};
+
+    fn candidates(
+        forced: Option<BackendKind>,
+        displays: Displays,
+        options: DialogOptions,
+    ) -> Vec<BackendKind> {
+        let config = Config {
+            backend: forced,
+            ..Default::default()
+        };
+        AutoBackend::new(config, displays).candidates(&options)
+    }
+
+    #[test]
+    fn prefers_layer_shell_then_gtk_then_tty() {
+        let displays = Displays {
+            wayland: true,
+            x11: true,
+        };
+        let options = DialogOptions {
+            tty_name: Some("/dev/pts/3".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            candidates(None, displays, options),
+            vec![BackendKind::LayerShell, BackendKind::Gtk, BackendKind::Tty]
+        );
+    }
+
+    #[test]
+    fn x11_and_client_displays_only_get_a_gtk_window() {
+        let x11 = Displays {
+            wayland: false,
+            x11: true,
+        };
+        assert_eq!(
+            candidates(None, x11, DialogOptions::default()),
+            vec![BackendKind::Gtk]
+        );
+
+        let options = DialogOptions {
+            display: Some(":0".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            candidates(None, Displays::default(), options),
+            vec![BackendKind::Gtk]
+        );
+    }
+
+    #[test]
+    fn client_display_is_only_used_without_our_own() {
+        let options = DialogOptions {
+            display: Some(":1".to_string()),
+            ..Default::default()
+        };
+        let backend = |displays| AutoBackend::new(Config::default(), displays);
+
+        assert_eq!(
+            backend(Displays::default()).client_display(&options),
+            Some(":1".to_string())
+        );
+        let x11 = Displays {
+            wayland: false,
+            x11: true,
+        };
+        assert_eq!(backend(x11).client_display(&options), None);
+    }
+
+    #[test]
+    fn forced_backend_is_the_only_candidate() {
+        let displays = Displays {
+            wayland: true,
+            x11: false,
+        };
+        assert_eq!(
+            candidates(Some(BackendKind::Tty), displays, DialogOptions::default()),
+            vec![BackendKind::Tty]
+        );
+    }
+
+    #[test]
+    fn reports_why_no_backend_could_be_shown() {
+        // /dev/null is no terminal, so the only candidate fails
+        let options = DialogOptions {
+            tty_name: Some("/dev/null".to_string()),
+            ..Default::default()
+        };
+        let mut backend = AutoBackend::new(Config::default(), Displays::default());
+
+        let result = backend.confirm(&options);
+        assert!(
+            matches!(result, Err(BackendError::Unavailable(reason)) if reason == "terminal: /dev/null is not a terminal")
+        );
+    }
+
+    #[test]
+    fn nothing_to_show_on_is_unavailable() {
+        let mut backend = AutoBackend::new(Config::default(), Displays::default());
+
+        let result = backend.message(&DialogOptions::default());
+        assert!(
+            matches!(result, Err(BackendError::Unavailable(reason)) if reason == "no display or terminal")
+        );
+    }
+
+    #[test]
+    fn reports_the_flavor_of_the_first_candidate() {
+        let backend = AutoBackend::new(Config::default(), Displays::default());
+        let tty = DialogOptions {
+            tty_name: Some("/dev/pts/3".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(backend.flavor(&tty), "tty");
+        assert_eq!(backend.flavor(&DialogOptions::default()), "gtk4");
+
+        let config = Config {
+            backend: Some(BackendKind::Scripted),
+            ..Default::default()
+        };
+        let scripted = AutoBackend::new(config, Displays::default());
+        assert_eq!(scripted.flavor(&tty), "scripted");
+    }
+
+    #[test]
+    fn sandbox_profiles_follow_the_backend() {
+        let mut config = Config::default();
+        config.scripted.pin = PinSource::File(PathBuf::from("/run/secrets/pin"));
+        let backend = AutoBackend::new(config, Displays::default());
+
+        let tty = backend.sandbox_profile(BackendKind::Tty);
+        assert_eq!(tty.readable, Vec::<PathBuf>::new());
+        assert_eq!(tty.writable, vec![PathBuf::from("/dev")]);
+        assert_eq!(tty.syscalls, Syscalls::Basic);
+
+        let scripted = backend.sandbox_profile(BackendKind::Scripted);
+        assert_eq!(scripted.readable, vec![PathBuf::from("/run/secrets/pin")]);
+        assert_eq!(scripted.writable, Vec::<PathBuf>::new());
+
+        let gtk = backend.sandbox_profile(BackendKind::Gtk);
+        assert!(gtk.readable.contains(&PathBuf::from("/usr")));
+        assert!(gtk.writable.starts_with(&[PathBuf::from("/dev")]));
+        assert_eq!(gtk.syscalls, Syscalls::Graphical);
+    }
+
+    #[test]
+    fn the_sandboxed_backend_is_the_only_candidate() {
+        let displays = Displays {
+            wayland: true,
+            x11: true,
+        };
+        let mut backend = AutoBackend::new(Config::default(), displays);
+        backend.sandboxed = Some(BackendKind::Gtk);
+
+        assert_eq!(
+            backend.candidates(&DialogOptions::default()),
+            vec![BackendKind::Gtk]
+        );
+    }
+
+    #[test]
+    fn parses_backend_names() {
+        assert_eq!("layer-shell".parse(), Ok(BackendKind::LayerShell));
+        assert_eq!("gtk".parse(), Ok(BackendKind::Gtk));
+        assert_eq!("tty".parse(), Ok(BackendKind::Tty));
+        assert_eq!("scripted".parse(), Ok(BackendKind::Scripted));
+        assert_eq!(
+            "qt".parse::<BackendKind>(),
+            Err("unknown backend `qt`".to_string())
+        );
+    }
+}
@@ -0,0 +1,966 @@
+use std::{
+    cell::{Cell, RefCell},
+    ffi::CString,
+    io,
+    os::fd::RawFd,
+    rc::Rc,
+};
+
+use gtk4::{gdk, glib, prelude::*};
+use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
+
+use crate::{generator::GeneratorOptions, secret::Secret, strength};
+
+use super::{
+    format_passphrase, layer_shell::LayerShellOptions, unformat_passphrase, BackendError,
+    ConfirmOutcome, DialogOptions, MessageOutcome, PinOutcome, PinentryBackend,
+};
+
+impl From<glib::BoolError> for BackendError {
+    fn from(error: glib::BoolError) -> Self {
+        BackendError::Unavailable(error.to_string())
+    }
+}
+
+/// A dialog that is currently shown. It reports its outcome exactly once,
+/// through the callback it was shown with.
+#[derive(Clone)]
+struct DialogHandle {
+    window: gtk4::Window,
+    /// The quality bar and the entry it rates.
+    quality_bar: Option<(gtk4::ProgressBar, gtk4::PasswordEntry)>,
+    /// Takes the client's verdict on a passphrase submitted for checking.
+    pin_checked: Option<Rc<dyn Fn(Option<String>)>>,
+}
+
+impl DialogHandle {
+    /// Shows a score from -100 to 100 in the quality bar, if there is one.
+    /// Without a score the passphrase is rated locally instead.
+    fn set_quality(&self, score: Option<i32>) {
+        match (&self.quality_bar, score) {
+            (Some((quality_bar, _)), Some(score)) => update_quality_bar(quality_bar, score),
+            (Some((quality_bar, entry)), None) => estimate_quality(quality_bar, &entry.text()),
+            (None, _) => {}
+        }
+    }
+
+    /// Returns the passphrase that was submitted for checking, or shows why
+    /// the client rejected it. Closing the dialog calls back into `done`.
+    fn set_pin_checked(&self, error: Option<String>) {
+        if let Some(pin_checked) = &self.pin_checked {
+            pin_checked(error);
+        }
+    }
+
+    fn close(&self) {
+        self.window.close();
+    }
+}
+
+/// How dialogs are put on screen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Presentation {
+    /// A regular modal window.
+    Window,
+    /// A layer-shell surface on the overlay layer that takes all keyboard
+    /// input until it is closed. Falls back to [`Presentation::Window`] where
+    /// layer-shell is not supported.
+    Overlay(LayerShellOptions),
+}
+
+impl Default for Presentation {
+    fn default() -> Self {
+        Presentation::Overlay(LayerShellOptions::default())
+    }
+}
+
+/// Shows each dialog as a GTK window and blocks until it is closed.
+#[derive(Debug, Default)]
+pub struct GtkBackend {
+    pub presentation: Presentation,
+    pub generator: GeneratorOptions,
+    /// The client's input, see [`PinentryBackend::watch_client`].
+    pub client: Option<RawFd>,
+    /// Shows dialogs on this display instead of the one from the environment.
+    pub display: Option<String>,
+}
+
+impl PinentryBackend for GtkBackend {
+    fn get_pin(
+        &mut self,
+        options: &DialogOptions,
+        quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+        check_pin: &mut dyn FnMut(&str) -> io::Result<Option<String>>,
+    ) -> Result<PinOutcome, BackendError> {
+        // the dialog only records what it needs, the client is asked between
+        // main loop iterations so the callbacks do not have to be 'static
+        let pending_quality = Rc::new(RefCell::new(None));
+        let pending_check = Rc::new(RefCell::new(None));
+        let result = Rc::new(RefCell::new(None));
+
+        let display = init(self.display.as_deref())?;
+        let dialog = show_pin(
+            options,
+            &display,
+            &self.presentation,
+            &self.generator,
+            {
+                let pending_quality = pending_quality.clone();
+                move |passphrase| *pending_quality.borrow_mut() = Some(Secret::from(passphrase))
+            },
+            {
+                let pending_check = pending_check.clone();
+                move |passphrase| *pending_check.borrow_mut() = Some(Secret::from(passphrase))
+            },
+            {
+                let result = result.clone();
+                move |outcome| *result.borrow_mut() = Some(outcome)
+            },
+        )?;
+
+        let mut io_error = None;
+        wait_for(&result, &dialog, self.client, || {
+            if let Some(passphrase) = pending_check.take() {
+                match check_pin(passphrase.expose()) {
+                    Ok(error) => dialog.set_pin_checked(error),
+                    Err(error) => {
+                        io_error = Some(error);
+                        dialog.close();
+                    }
+                }
+            }
+            if let Some(passphrase) = pending_quality.take() {
+                match quality(passphrase.expose()) {
+                    Ok(score) => dialog.set_quality(score),
+                    Err(error) => {
+                        io_error = Some(error);
+                        dialog.close();
+                    }
+                }
+            }
+        })?;
+
+        match io_error {
+            Some(error) => Err(BackendError::Io(error)),
+            None => Ok(result.take().unwrap_or(PinOutcome::Canceled)),
+        }
+    }
+
+    fn confirm(&mut self, options: &DialogOptions) -> Result<ConfirmOutcome, BackendError> {
+        let result = Rc::new(RefCell::new(None));
+        let display = init(self.display.as_deref())?;
+        let dialog = show_confirm(options, &display, &self.presentation, {
+            let result = result.clone();
+            move |outcome| *result.borrow_mut() = Some(outcome)
+        })?;
+
+        wait_for(&result, &dialog, self.client, || {})?;
+        Ok(result.take().unwrap_or(ConfirmOutcome::Canceled))
+    }
+
+    fn message(&mut self, options: &DialogOptions) -> Result<MessageOutcome, BackendError> {
+        let result = Rc::new(RefCell::new(None));
+        let display = init(self.display.as_deref())?;
+        let dialog = show_message(options, &display, &self.presentation, {
+            let result = result.clone();
+            move |outcome| *result.borrow_mut() = Some(outcome)
+        })?;
+
+        wait_for(&result, &dialog, self.client, || {})?;
+        Ok(result.take().unwrap_or(MessageOutcome::Dismissed))
+    }
+
+    fn flavor(&self, _options: &DialogOptions) -> &'static str {
+        "gtk4"
+    }
+
+    fn watch_client(&mut self, fd: RawFd) {
+        self.client = Some(fd);
+    }
+
+    fn prepare(&mut self, _options: &DialogOptions) -> Result<(), BackendError> {
+        init(self.display.as_deref())?;
+        Ok(())
+    }
+}
+
+/// Initializes GTK and returns the display called `name`, or the one from the
+/// environment.
+fn init(name: Option<&str>) -> Result<gdk::Display, glib::BoolError> {
+    // init fails without a default display, so one that is named before then
+    // has to become the default
+    if let (Some(name), false) = (name, gtk4::is_initialized_main_thread()) {
+        open_default_display(name)?;
+    }
+    gtk4::init()?;
+
+    let Some(name) = name else {
+        return gdk::Display::default().ok_or_else(|| glib::bool_error!("no default display"));
+    };
+    let opened = gdk::DisplayManager::get()
+        .list_displays()
+        .into_iter()
+        .find(|display| display.name() == name);
+    match opened {
+        Some(display) => Ok(display),
+        None => gdk::Display::open(Some(name))
+            .ok_or_else(|| glib::bool_error!("cannot open display {}", name)),
+    }
+}
+
+/// Opens `name` as GTK's default display. The bindings refuse to open
+/// displays before GTK is initialized, so this goes through GDK directly.
+fn open_default_display(name: &str) -> Result<(), glib::BoolError> {
+    let c_name = CString::new(name).map_err(|_| glib::bool_error!("invalid display name"))?;
+    unsafe {
+        let manager = gdk::ffi::gdk_display_manager_get();
+        let display = gdk::ffi::gdk_display_manager_open_display(manager, c_name.as_ptr());
+        if display.is_null() {
+            return Err(glib::bool_error!("cannot open display {}", name));
+        }
+        gdk::ffi::gdk_display_manager_set_default_display(manager, display);
+    }
+    Ok(())
+}
+
+/// Shows a passphrase dialog without waiting for it. `quality` is called
+/// whenever the quality bar needs a new score for a non-empty passphrase, and
+/// `generator` makes the passphrases offered by `SETGENPIN`. Under
+/// `constraints-enforce`, `check_pin` is called with the submitted passphrase
+/// and the dialog stays open until [`DialogHandle::set_pin_checked`].
+fn show_pin(
+    options: &DialogOptions,
+    display: &gdk::Display,
+    presentation: &Presentation,
+    generator: &GeneratorOptions,
+    quality: impl Fn(&str) + 'static,
+    check_pin: impl Fn(&str) + 'static,
+    done: impl FnOnce(PinOutcome) + 'static,
+) -> Result<DialogHandle, glib::BoolError> {
+    let outcome = Rc::new(RefCell::new(Some(PinOutcome::Canceled)));
+
+    let window = build_window(options, display, presentation);
+    let container = build_container(options);
+
+    // keeps the entries aligned when there are two of them
+    let prompts = gtk4::SizeGroup::new(gtk4::SizeGroupMode::Horizontal);
+    let entry = build_password_entry();
+    container.append(&build_entry_row(
+        options.prompt.as_deref(),
+        &entry,
+        &prompts,
+    ));
+    if let Some(hint) = options
+        .formatted_passphrase_hint
+        .as_deref()
+        .filter(|_| options.formatted_passphrase)
+    {
+        container.append(&build_hint_label(hint));
+    }
+    if let Some(hint) = &options.constraints_hint_short {
+        let hint = build_hint_label(hint);
+        hint.set_tooltip_text(options.constraints_hint_long.as_deref());
+        container.append(&hint);
+    }
+    let rejected = build_status_label("", "error");
+    container.append(&rejected);
+
+    let quality_bar = options.quality_bar.then(|| {
+        let quality_bar = gtk4::ProgressBar::new();
+        quality_bar.set_show_text(true);
+        quality_bar.set_text(Some("Quality"));
+        quality_bar.set_tooltip_text(options.quality_bar_tooltip.as_deref());
+        container.append(&quality_bar);
+
+        entry.connect_changed({
+            let quality_bar = quality_bar.clone();
+            move |entry| {
+                let passphrase = entry.text();
+                if passphrase.is_empty() {
+                    update_quality_bar(&quality_bar, 0);
+                } else {
+                    quality(&passphrase);
+                }
+            }
+        });
+
+        (quality_bar, entry.clone())
+    });
+
+    let repeat = options.repeat.then(|| {
+        let repeat_entry = build_password_entry();
+        container.append(&build_entry_row(
+            Some(options.repeat_prompt.as_deref().unwrap_or("Repeat:")),
+            &repeat_entry,
+            &prompts,
+        ));
+
+        let mismatch = build_status_label(
+            options
+                .repeat_error
+                .as_deref()
+                .unwrap_or("Passphrases do not match"),
+            "error",
+        );
+        container.append(&mismatch);
+        let matched = options.repeat_ok.as_deref().map(|ok| {
+            let matched = build_status_label(ok, "success");
+            container.append(&matched);
+            matched
+        });
+
+        let update = {
+            let entry = entry.clone();
+            let repeat_entry = repeat_entry.clone();
+            let mismatch = mismatch.clone();
+            move |_: &gtk4::PasswordEntry| {
+                mismatch.set_visible(false);
+                if let Some(matched) = &matched {
+                    let passphrase = entry.text();
+                    matched
+                        .set_visible(!passphrase.is_empty() && passphrase == repeat_entry.text());
+                }
+            }
+        };
+        entry.connect_changed(update.clone());
+        repeat_entry.connect_changed(update);
+
+        (repeat_entry, mismatch)
+    });
+
+    // the entries cannot show a passphrase in a way that can be noted down,
+    // so it is shown below them when asked to
+    let reveal = (options.generate_pin || options.formatted_passphrase).then(|| {
+        let reveal = gtk4::CheckButton::with_mnemonic("_Show passphrase");
+        container.append(&reveal);
+        let revealed = build_status_label("", "monospace");
+        revealed.set_selectable(true);
+        container.append(&revealed);
+
+        // the peek icons would show it without the formatting
+        entry.set_show_peek_icon(false);
+        if let Some((repeat_entry, _)) = &repeat {
+            repeat_entry.set_show_peek_icon(false);
+        }
+
+        let formatted = options.formatted_passphrase;
+        let update = {
+            let entry = entry.clone();
+            let reveal = reveal.clone();
+            move || {
+                let passphrase = entry_passphrase(&entry, formatted);
+                if reveal.is_active() && !passphrase.is_empty() {
+                    let shown = if formatted {
+                        format_passphrase(passphrase.expose())
+                    } else {
+                        passphrase
+                    };
+                    revealed.set_text(shown.expose());
+                    revealed.set_visible(true);
+                } else {
+                    revealed.set_text("");
+                    revealed.set_visible(false);
+                }
+            }
+        };
+        entry.connect_changed({
+            let update = update.clone();
+            move |_| update()
+        });
+        reveal.connect_toggled(move |_| update());
+
+        reveal
+    });
+
+    let generate_button = options.generate_pin.then(|| {
+        let generate_button = gtk4::Button::with_mnemonic("_Generate");
+        generate_button.set_tooltip_text(options.generate_pin_tooltip.as_deref());
+        let generate_error = build_status_label("", "error");
+        container.append(&generate_error);
+
+        generate_button.connect_clicked({
+            let entry = entry.clone();
+            let repeat_entry = repeat
+                .as_ref()
+                .map(|(repeat_entry, _)| repeat_entry.clone());
+            let reveal = reveal.clone();
+            let generator = generator.clone();
+            move |_| match generator.generate() {
+                Ok(passphrase) => {
+                    generate_error.set_visible(false);
+                    entry.set_text(passphrase.expose());
+                    if let Some(repeat_entry) = &repeat_entry {
+                        repeat_entry.set_text(passphrase.expose());
+                    }
+                    // it has to be noted down
+                    if let Some(reveal) = &reveal {
+                        reveal.set_active(true);
+                    }
+                }
+                Err(error) => {
+                    generate_error.set_text(&format!("Cannot generate a passphrase: {}", error));
+                    generate_error.set_visible(true);
+                }
+            }
+        });
+
+        generate_button
+    });
+
+    let countdown = options.timeout.map(|seconds| {
+        Countdown::start(&container, seconds, {
+            let window = window.clone();
+            let outcome = outcome.clone();
+            move || {
+                *outcome.borrow_mut() = Some(PinOutcome::TimedOut);
+                window.close();
+            }
+        })
+    });
+
+    let cancel_button =
+        gtk4::Button::with_mnemonic(options.cancel_button.as_deref().unwrap_or("_Cancel"));
+    let ok_button = gtk4::Button::with_mnemonic(options.ok_button.as_deref().unwrap_or("_OK"));
+    ok_button.add_css_class("suggested-action");
+    let buttons = match &generate_button {
+        Some(generate_button) => vec![generate_button, &cancel_button, &ok_button],
+        None => vec![&cancel_button, &ok_button],
+    };
+    container.append(&build_button_row(&buttons));
+
+    // the passphrase waiting for the client's verdict
+    let checking = Rc::new(RefCell::new(None));
+    let pin_checked = options.constraints_enforce.then(|| {
+        let window = window.clone();
+        let entry = entry.clone();
+        let ok_button = ok_button.clone();
+        let outcome = outcome.clone();
+        let checking = checking.clone();
+        let options = options.clone();
+        Rc::new(move |error: Option<String>| {
+            let checked = checking.borrow_mut().take();
+            let Some(checked) = checked else {
+                return;
+            };
+            ok_button.set_sensitive(true);
+            match error {
+                None => {
+                    *outcome.borrow_mut() = Some(checked);
+                    window.close();
+                }
+                Some(error) => {
+                    rejected.set_text(&options.constraints_error(&error));
+                    rejected.set_visible(true);
+                    entry.grab_focus();
+                }
+            }
+        }) as Rc<dyn Fn(Option<String>)>
+    });
+
+    let submit = {
+        let window = window.clone();
+        let entry = entry.clone();
+        let repeat = repeat.clone();
+        let ok_button = ok_button.clone();
+        let outcome = outcome.clone();
+        let constraints_enforce = options.constraints_enforce;
+        let check_pin = Rc::new(check_pin);
+        let formatted = options.formatted_passphrase;
+        move || {
+            if checking.borrow().is_some() {
+                return;
+            }
+
+            let passphrase = entry_passphrase(&entry, formatted);
+            let repeated = match &repeat {
+                None => false,
+                Some((repeat_entry, _))
+                    if entry_passphrase(repeat_entry, formatted) == passphrase =>
+                {
+                    true
+                }
+                Some((repeat_entry, mismatch)) => {
+                    mismatch.set_visible(true);
+                    repeat_entry.grab_focus();
+                    return;
+                }
+            };
+
+            let checked = constraints_enforce.then(|| passphrase.clone());
+            let pin = if repeated {
+                PinOutcome::Repeated(passphrase)
+            } else {
+                PinOutcome::Pin(passphrase)
+            };
+            if let Some(checked) = checked {
+                ok_button.set_sensitive(false);
+                *checking.borrow_mut() = Some(pin);
+                check_pin(checked.expose());
+                return;
+            }
+            *outcome.borrow_mut() = Some(pin);
+            window.close();
+        }
+    };
+    entry.connect_activate({
+        let submit = submit.clone();
+        let repeat_entry = repeat
+            .as_ref()
+            .map(|(repeat_entry, _)| repeat_entry.clone());
+        move |_| match &repeat_entry {
+            Some(repeat_entry) => {
+                repeat_entry.grab_focus();
+            }
+            None => submit(),
+        }
+    });
+    if let Some((repeat_entry, _)) = &repeat {
+        repeat_entry.connect_activate({
+            let submit = submit.clone();
+            move |_| submit()
+        });
+    }
+    ok_button.connect_clicked(move |_| submit());
+    cancel_button.connect_clicked({
+        let window = window.clone();
+        move |_| window.close()
+    });
+
+    window.set_child(Some(&container));
+    GtkWindowExt::set_focus(&window, Some(&entry));
+    present(&window, outcome, countdown, done);
+
+    Ok(DialogHandle {
+        window,
+        quality_bar,
+        pin_checked,
+    })
+}
+
+fn show_confirm(
+    options: &DialogOptions,
+    display: &gdk::Display,
+    presentation: &Presentation,
+    done: impl FnOnce(ConfirmOutcome) + 'static,
+) -> Result<DialogHandle, glib::BoolError> {
+    let outcome = Rc::new(RefCell::new(Some(ConfirmOutcome::Canceled)));
+
+    let window = build_window(options, display, presentation);
+    let container = build_container(options);
+
+    let countdown = options.timeout.map(|seconds| {
+        Countdown::start(&container, seconds, {
+            let window = window.clone();
+            let outcome = outcome.clone();
+            move || {
+                *outcome.borrow_mut() = Some(ConfirmOutcome::TimedOut);
+                window.close();
+            }
+        })
+    });
+
+    let mut buttons = vec![];
+
+    let cancel_button =
+        gtk4::Button::with_mnemonic(options.cancel_button.as_deref().unwrap_or("_Cancel"));
+    buttons.push(cancel_button.clone());
+
+    if let Some(not_ok) = &options.not_ok_button {
+        let not_ok_button = gtk4::Button::with_mnemonic(not_ok);
+        not_ok_button.connect_clicked({
+            let window = window.clone();
+            let outcome = outcome.clone();
+            move |_| {
+                *outcome.borrow_mut() = Some(ConfirmOutcome::NotConfirmed);
+                window.close();
+            }
+        });
+        buttons.push(not_ok_button);
+    }
+
+    let ok_button = gtk4::Button::with_mnemonic(options.ok_button.as_deref().unwrap_or("_OK"));
+    ok_button.add_css_class("suggested-action");
+    ok_button.connect_clicked({
+        let window = window.clone();
+        let outcome = outcome.clone();
+        move |_| {
+            *outcome.borrow_mut() = Some(ConfirmOutcome::Confirmed);
+            window.close();
+        }
+    });
+    buttons.push(ok_button.clone());
+
+    cancel_button.connect_clicked({
+        let window = window.clone();
+        move |_| window.close()
+    });
+
+    container.append(&build_button_row(&buttons.iter().collect::<Vec<_>>()));
+    window.set_child(Some(&container));
+    GtkWindowExt::set_focus(&window, Some(&ok_button));
+    present(&window, outcome, countdown, done);
+
+    Ok(DialogHandle {
+        window,
+        quality_bar: None,
+        pin_checked: None,
+    })
+}
+
+fn show_message(
+    options: &DialogOptions,
+    display: &gdk::Display,
+    presentation: &Presentation,
+    done: impl FnOnce(MessageOutcome) + 'static,
+) -> Result<DialogHandle, glib::BoolError> {
+    let outcome = Rc::new(RefCell::new(Some(MessageOutcome::Dismissed)));
+
+    let window = build_window(options, display, presentation);
+    let container = build_container(options);
+
+    let countdown = options.timeout.map(|seconds| {
+        Countdown::start(&container, seconds, {
+            let window = window.clone();
+            let outcome = outcome.clone();
+            move || {
+                *outcome.borrow_mut() = Some(MessageOutcome::TimedOut);
+                window.close();
+            }
+        })
+    });
+
+    let ok_button = gtk4::Button::with_mnemonic(options.ok_button.as_deref().unwrap_or("_OK"));
+    ok_button.add_css_class("suggested-action");
+    ok_button.connect_clicked({
+        let window = window.clone();
+        move |_| window.close()
+    });
+
+    container.append(&build_button_row(&[&ok_button]));
+    window.set_child(Some(&container));
+    GtkWindowExt::set_focus(&window, Some(&ok_button));
+    present(&window, outcome, countdown, done);
+
+    Ok(DialogHandle {
+        window,
+        quality_bar: None,
+        pin_checked: None,
+    })
+}
+
+/// Shows the remaining seconds below the dialog content and calls `expired`
+/// once they have run out. Dropping it stops the countdown.
+struct Countdown {
+    source: Rc<RefCell<Option<glib::SourceId>>>,
+}
+
+impl Countdown {
+    fn start(container: &gtk4::Box, seconds: u32, expired: impl Fn() + 'static) -> Countdown {
+        let label = gtk4::Label::new(Some(&countdown_text(seconds)));
+        label.set_xalign(0.0);
+        label.add_css_class("dim-label");
+        container.append(&label);
+
+        let remaining = Cell::new(seconds);
+        let source = Rc::new(RefCell::new(None));
+        let source_id = glib::timeout_add_seconds_local(1, {
+            let source = source.clone();
+            move || {
+                let seconds = remaining.get().saturating_sub(1);
+                remaining.set(seconds);
+
+                if seconds == 0 {
+                    source.borrow_mut().take();
+                    expired();
+                    return glib::ControlFlow::Break;
+                }
+
+                label.set_text(&countdown_text(seconds));
+                glib::ControlFlow::Continue
+            }
+        });
+        source.borrow_mut().replace(source_id);
+
+        Countdown { source }
+    }
+}
+
+impl Drop for Countdown {
+    fn drop(&mut self) {
+        if let Some(source) = self.source.borrow_mut().take() {
+            source.remove();
+        }
+    }
+}
+
+fn countdown_text(seconds: u32) -> String {
+    match seconds {
+        1 => "Closing in 1 second".to_string(),
+        _ => format!("Closing in {} seconds", seconds),
+    }
+}
+
+fn build_window(
+    options: &DialogOptions,
+    display: &gdk::Display,
+    presentation: &Presentation,
+) -> gtk4::Window {
+    let window = gtk4::Window::builder()
+        .title(options.title.as_deref().unwrap_or("Pinentry"))
+        .display(display)
+        .modal(true)
+        .resizable(false)
+        .build();
+
+    if let Presentation::Overlay(layer_shell) = presentation {
+        // GNOME and X11 sessions have no layer-shell, the window stays regular
+        if gtk4_layer_shell::is_supported() {
+            show_as_overlay(&window, layer_shell);
+        }
+    }
+
+    window
+}
+
+/// Turns `window` into an overlay surface before it is presented, so no other
+/// client gets keyboard input while it is shown.
+fn show_as_overlay(window: &gtk4::Window, options: &LayerShellOptions) {
+    window.init_layer_shell();
+    window.set_namespace("pinentry");
+    window.set_layer(Layer::Overlay);
+    window.set_keyboard_mode(KeyboardMode::Exclusive);
+
+    let edges = [
+        (Edge::Top, options.anchor.top, options.margins.top),
+        (Edge::Right, options.anchor.right, options.margins.right),
+        (Edge::Bottom, options.anchor.bottom, options.margins.bottom),
+        (Edge::Left, options.anchor.left, options.margins.left),
+    ];
+    for (edge, anchored, margin) in edges {
+        window.set_anchor(edge, anchored);
+        window.set_margin(edge, margin);
+    }
+
+    let monitor = options.output.as_deref().and_then(find_monitor);
+    if let Some(monitor) = &monitor {
+        window.set_monitor(monitor);
+    }
+
+    if options.backdrop {
+        // presented first, so the dialog ends up on top of it
+        let backdrop = build_backdrop(monitor.as_ref());
+        backdrop.present();
+        window.connect_close_request(move |_| {
+            backdrop.close();
+            glib::Propagation::Proceed
+        });
+    }
+}
+
+fn find_monitor(connector: &str) -> Option<gdk::Monitor> {
+    let monitors = gdk::Display::default()?.monitors();
+    (0..monitors.n_items())
+        .filter_map(|position| monitors.item(position)?.downcast::<gdk::Monitor>().ok())
+        .find(|monitor| monitor.connector().as_deref() == Some(connector))
+}
+
+/// A dimmed surface covering the whole output, without keyboard input.
+fn build_backdrop(monitor: Option<&gdk::Monitor>) -> gtk4::Window {
+    let backdrop = gtk4::Window::new();
+    backdrop.init_layer_shell();
+    backdrop.set_namespace("pinentry-backdrop");
+    backdrop.set_layer(Layer::Overlay);
+    backdrop.set_keyboard_mode(KeyboardMode::None);
+    backdrop.set_exclusive_zone(-1);
+    for edge in [Edge::Top, Edge::Right, Edge::Bottom, Edge::Left] {
+        backdrop.set_anchor(edge, true);
+    }
+    if let Some(monitor) = monitor {
+        backdrop.set_monitor(monitor);
+    }
+
+    thread_local! {
+        static STYLED: Cell<bool> = const { Cell::new(false) };
+    }
+    if let (false, Some(display)) = (STYLED.get(), gdk::Display::default()) {
+        let provider = gtk4::CssProvider::new();
+        provider
+            .load_from_data("window.pinentry-backdrop { background-color: rgba(0, 0, 0, 0.5); }");
+        gtk4::style_context_add_provider_for_display(
+            &display,
+            &provider,
+            gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+        STYLED.set(true);
+    }
+    backdrop.add_css_class("pinentry-backdrop");
+
+    backdrop
+}
+
+fn build_container(options: &DialogOptions) -> gtk4::Box {
+    let container = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+    container.set_margin_top(18);
+    container.set_margin_bottom(18);
+    container.set_margin_start(18);
+    container.set_margin_end(18);
+
+    if let Some(description) = &options.description {
+        let label = gtk4::Label::new(Some(description));
+        label.set_wrap(true);
+        label.set_xalign(0.0);
+        container.append(&label);
+    }
+
+    if let Some(error) = &options.error {
+        let label = gtk4::Label::new(Some(error));
+        label.set_wrap(true);
+        label.set_xalign(0.0);
+        label.add_css_class("error");
+        container.append(&label);
+    }
+
+    container
+}
+
+fn build_password_entry() -> gtk4::PasswordEntry {
+    let entry = gtk4::PasswordEntry::new();
+    entry.set_show_peek_icon(true);
+    entry.set_hexpand(true);
+    entry
+}
+
+fn build_entry_row(
+    prompt: Option<&str>,
+    entry: &gtk4::PasswordEntry,
+    prompts: &gtk4::SizeGroup,
+) -> gtk4::Box {
+    let row = gtk4::Box::new(gtk4::Orientation::Horizontal, 12);
+    if let Some(prompt) = prompt {
+        let label = gtk4::Label::with_mnemonic(prompt);
+        label.set_xalign(0.0);
+        label.set_mnemonic_widget(Some(entry));
+        prompts.add_widget(&label);
+        row.append(&label);
+    }
+    row.append(entry);
+    row
+}
+
+/// What was typed into `entry`, without the spaces a formatted passphrase is
+/// shown with, in case they were copied along.
+fn entry_passphrase(entry: &gtk4::PasswordEntry, formatted: bool) -> Secret {
+    let text = entry.text();
+    if formatted {
+        unformat_passphrase(&text)
+    } else {
+        Secret::from(text.as_str())
+    }
+}
+
+fn build_hint_label(text: &str) -> gtk4::Label {
+    let label = gtk4::Label::new(Some(text));
+    label.set_wrap(true);
+    label.set_xalign(0.0);
+    label.add_css_class("dim-label");
+    label
+}
+
+/// A line of feedback below the entries, hidden until it applies.
+fn build_status_label(text: &str, css_class: &str) -> gtk4::Label {
+    let label = gtk4::Label::new(Some(text));
+    label.set_wrap(true);
+    label.set_xalign(0.0);
+    label.add_css_class(css_class);
+    label.set_visible(false);
+    label
+}
+
+fn build_button_row(buttons: &[&gtk4::Button]) -> gtk4::Box {
+    let row = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+    row.set_halign(gtk4::Align::End);
+    for button in buttons {
+        row.append(*button);
+    }
+    row
+}
+
+/// Shows `window` and hands the outcome to `done` once it is closed, however
+/// that happens.
+fn present<T: 'static>(
+    window: &gtk4::Window,
+    outcome: Rc<RefCell<Option<T>>>,
+    countdown: Option<Countdown>,
+    done: impl FnOnce(T) + 'static,
+) {
+    let countdown = RefCell::new(countdown);
+    let done = RefCell::new(Some(done));
+    window.connect_close_request(move |_| {
+        countdown.borrow_mut().take();
+        if let (Some(done), Some(outcome)) = (done.borrow_mut().take(), outcome.take()) {
+            done(outcome);
+        }
+        glib::Propagation::Proceed
+    });
+
+    window.present();
+}
+
+/// Iterates the main context until `result` is filled in, calling
+/// `after_iteration` after every iteration. If `client` hangs up first,
+/// `dialog` is closed and the wait fails with an unexpected EOF.
+fn wait_for<T>(
+    result: &Rc<RefCell<Option<T>>>,
+    dialog: &DialogHandle,
+    client: Option<RawFd>,
+    mut after_iteration: impl FnMut(),
+) -> Result<(), BackendError> {
+    let hung_up = Rc::new(Cell::new(false));
+    let watch = client.map(|fd| {
+        let hung_up = hung_up.clone();
+        let condition = glib::IOCondition::HUP | glib::IOCondition::ERR;
+        glib::unix_fd_add_local(fd, condition, move |_, _| {
+            hung_up.set(true);
+            glib::ControlFlow::Break
+        })
+    });
+
+    let context = glib::MainContext::default();
+    while result.borrow().is_none() {
+        context.iteration(true);
+        if hung_up.get() {
+            // nobody is left to take the answer
+            dialog.close();
+            return Err(BackendError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        after_iteration();
+    }
+
+    // a watch that fired has removed itself
+    if let (Some(watch), false) = (watch, hung_up.get()) {
+        watch.remove();
+    }
+    Ok(())
+}
+
+/// Negative scores mean the passphrase is not acceptable and are shown as an
+/// error.
+fn update_quality_bar(quality_bar: &gtk4::ProgressBar, score: i32) {
+    let score = score.clamp(-100, 100);
+    quality_bar.set_fraction(f64::from(score.unsigned_abs()) / 100.0);
+    quality_bar.set_text(Some("Quality"));
+    if score < 0 {
+        quality_bar.add_css_class("error");
+    } else {
+        quality_bar.remove_css_class("error");
+    }
+}
+
+/// Rates `passphrase` without gpg-agent and says what makes it weak.
+fn estimate_quality(quality_bar: &gtk4::ProgressBar, passphrase: &str) {
+    let strength = strength::estimate(passphrase);
+    update_quality_bar(quality_bar, strength.score);
+    if let Some(explanation) = strength.explanation() {
+        quality_bar.set_text(Some(&explanation));
+    }
+}
@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+/// Where the overlay dialog is put on its output and what surrounds it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LayerShellOptions {
+    pub anchor: Anchor,
+    pub margins: Margins,
+    /// The connector name of the output to show the dialog on, e.g. `DP-1`.
+    /// The compositor picks one if this is not set or does not match.
+    pub output: Option<String>,
+    /// Dims everything else on the output behind the dialog.
+    pub backdrop: bool,
+}
+
+/// The edges the dialog is attached to. No edges centers it.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Anchor {
+    pub top: bool,
+    pub bottom: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl FromStr for Anchor {
+    type Err = String;
+
+    /// Parses a comma separated list of edges such as `top,right`, or
+    /// `center`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut anchor = Anchor::default();
+        for edge in input.split(',').map(str::trim) {
+            match edge {
+                "center" | "" => {}
+                "top" => anchor.top = true,
+                "bottom" => anchor.bottom = true,
+                "left" => anchor.left = true,
+                "right" => anchor.right = true,
+                _ => return Err(format!("unknown edge `{}`", edge)),
+            }
+        }
+        Ok(anchor)
+    }
+}
+
+/// Distances from the anchored edges, in pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Margins {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+impl FromStr for Margins {
+    type Err = String;
+
+    /// Takes one, two or four comma separated values, in the same order as
+    /// CSS margins.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let values = input
+            .split(',')
+            .map(|value| {
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid margin `{}`", value.trim()))
+            })
+            .collect::<Result<Vec<i32>, _>>()?;
+
+        match values[..] {
+            [all] => Ok(Margins {
+                top: all,
+                right: all,
+                bottom: all,
+                left: all,
+            }),
+            [vertical, horizontal] => Ok(Margins {
+                top: vertical,
+                right: horizontal,
+                bottom: vertical,
+                left: horizontal,
+            }),
+            [top, right, bottom, left] => Ok(Margins {
+                top,
+                right,
+                bottom,
+                left,
+            }),
+            _ => Err(format!("expected 1, 2 or 4 margins, got {}", values.len())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_anchor_edges() {
+        assert_eq!(
+            "top, right".parse(),
+            Ok(Anchor {
+                top: true,
+                right: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!("center".parse(), Ok(Anchor::default()));
+    }
+
+    #[test]
+    fn rejects_unknown_anchor_edges() {
+        assert_eq!(
+            "top,middle".parse::<Anchor>(),
+            Err("unknown edge `middle`".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_margins_like_css() {
+        assert_eq!(
+            "8".parse(),
+            Ok(Margins {
+                top: 8,
+                right: 8,
+                bottom: 8,
+                left: 8,
+            })
+        );
+        assert_eq!(
+            "8, 16".parse(),
+            Ok(Margins {
+                top: 8,
+                right: 16,
+                bottom: 8,
+                left: 16,
+            })
+        );
+        assert_eq!(
+            "1,2,3,4".parse(),
+            Ok(Margins {
+                top: 1,
+                right: 2,
+                bottom: 3,
+                left: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_margins() {
+        assert_eq!(
+            "8,x".parse::<Margins>(),
+            Err("invalid margin `x`".to_string())
+        );
+        assert_eq!(
+            "1,2,3".parse::<Margins>(),
+            Err("expected 1, 2 or 4 margins, got 3".to_string())
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use std::{fmt::Display, io, os::fd::RawFd};
+
+use crate::secret::Secret;
+
+pub mod auto;
+pub mod gtk;
+pub mod layer_shell;
+pub mod scripted;
+pub mod tty;
+
+/// A snapshot of the session settings a dialog is shown with, copied out of
+/// the session when the dialog is requested.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DialogOptions {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub prompt: Option<String>,
+    pub ok_button: Option<String>,
+    pub cancel_button: Option<String>,
+    pub not_ok_button: Option<String>,
+    pub error: Option<String>,
+    pub repeat: bool,
+    pub repeat_prompt: Option<String>,
+    pub repeat_error: Option<String>,
+    pub repeat_ok: Option<String>,
+    pub quality_bar: bool,
+    pub quality_bar_tooltip: Option<String>,
+    /// gpg-agent rejects passphrases that fail its checks, so the dialog has
+    /// them checked with `CHECKPIN` before returning one.
+    pub constraints_enforce: bool,
+    pub constraints_hint_short: Option<String>,
+    pub constraints_hint_long: Option<String>,
+    pub constraints_error_title: Option<String>,
+    pub generate_pin: bool,
+    pub generate_pin_tooltip: Option<String>,
+    pub key_info: Option<String>,
+    /// Show passphrases outside of the entry in groups of five characters.
+    pub formatted_passphrase: bool,
+    pub formatted_passphrase_hint: Option<String>,
+    pub timeout: Option<u32>,
+    pub tty_name: Option<String>,
+    pub tty_type: Option<String>,
+    pub display: Option<String>,
+}
+
+impl DialogOptions {
+    /// The error to show when the client rejected a passphrase for not
+    /// meeting its constraints.
+    pub fn constraints_error(&self, error: &str) -> String {
+        match &self.constraints_error_title {
+            Some(title) => format!("{}\n{}", title, error),
+            None => error.to_string(),
+        }
+    }
+}
+
+/// Splits `passphrase` into groups of five characters separated by thin
+/// spaces, to make it easier to read off the screen.
+pub fn format_passphrase(passphrase: &str) -> Secret {
+    let mut formatted = Secret::new();
+    for (position, character) in passphrase.chars().enumerate() {
+        if position > 0 && position % 5 == 0 {
+            formatted.push(THIN_SPACE);
+        }
+        formatted.push(character);
+    }
+    formatted
+}
+
+/// Undoes [`format_passphrase`], for formatted passphrases that were pasted
+/// back into the entry. Anything else is returned as it is, thin spaces the
+/// user typed are part of the passphrase.
+pub fn unformat_passphrase(passphrase: &str) -> Secret {
+    let mut unformatted = Secret::new();
+    for (position, character) in passphrase.chars().enumerate() {
+        // every sixth character is a separator after a group of five
+        if position % 6 != 5 {
+            unformatted.push(character);
+        }
+    }
+
+    if format_passphrase(unformatted.expose()).expose() == passphrase {
+        unformatted
+    } else {
+        Secret::from(passphrase)
+    }
+}
+
+const THIN_SPACE: char = '\u{2009}';
+
+#[derive(Debug, PartialEq)]
+pub enum PinOutcome {
+    Pin(Secret),
+    /// Entered twice and both times the same, as asked for with `SETREPEAT`.
+    Repeated(Secret),
+    Canceled,
+    TimedOut,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfirmOutcome {
+    Confirmed,
+    NotConfirmed,
+    Canceled,
+    TimedOut,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MessageOutcome {
+    Dismissed,
+    TimedOut,
+}
+
+#[derive(Debug)]
+pub enum BackendError {
+    /// The backend cannot show anything, e.g. there is no display.
+    Unavailable(String),
+    /// Talking to the client or the user failed while the dialog was open.
+    Io(io::Error),
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::Unavailable(reason) => write!(f, "backend unavailable: {}", reason),
+            BackendError::Io(error) => write!(f, "I/O error: {}", error),
+        }
+    }
+}
+
+/// Presents the dialogs a client asks for. Each call blocks until the user
+/// has answered.
+pub trait PinentryBackend {
+    /// Asks for a passphrase. While the quality bar is shown, `quality` can be
+    /// called with the current passphrase and returns its score from -100 to
+    /// 100, or `None` if it could not be determined. Under
+    /// `constraints-enforce`, `check_pin` is called with the passphrase the
+    /// user entered while the dialog is still open. It returns why the client
+    /// rejects it, which is shown before asking again.
+    fn get_pin(
+        &mut self,
+        options: &DialogOptions,
+        quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+        check_pin: &mut dyn FnMut(&str) -> io::Result<Option<String>>,
+    ) -> Result<PinOutcome, BackendError>;
+
+    fn confirm(&mut self, options: &DialogOptions) -> Result<ConfirmOutcome, BackendError>;
+
+    /// Shows the description with a single button. Used for `MESSAGE` and
+    /// `CONFIRM --one-button`.
+    fn message(&mut self, options: &DialogOptions) -> Result<MessageOutcome, BackendError>;
+
+    /// What `GETINFO flavor` reports while a dialog with `options` would be
+    /// shown by this backend.
+    fn flavor(&self, options: &DialogOptions) -> &'static str;
+
+    /// Watches the client's input on `fd` while a dialog is open. If the
+    /// client hangs up, the dialog is closed and fails with an
+    /// `UnexpectedEof` I/O error. Backends that never wait for the user
+    /// ignore this.
+    fn watch_client(&mut self, _fd: RawFd) {}
+
+    /// Gets everything ready to show a dialog with `options`, like the
+    /// connection to the display, and fails if that is not possible.
+    /// [`AutoBackend`](auto::AutoBackend) applies the sandbox after this.
+    fn prepare(&mut self, _options: &DialogOptions) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+impl<B: PinentryBackend + ?Sized> PinentryBackend for Box<B> {
+    fn get_pin(
+        &mut self,
+        options: &DialogOptions,
+        quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+        check_pin: &mut dyn FnMut(&str) -> io::Result<Option<String>>,
+    ) -> Result<PinOutcome, BackendError> {
+        (**self).get_pin(options, quality, check_pin)
+    }
+
+    fn confirm(&mut self, options: &DialogOptions) -> Result<ConfirmOutcome, BackendError> {
+        (**self).confirm(options)
+    }
+
+    fn message(&mut self, options: &DialogOptions) -> Result<MessageOutcome, BackendError> {
+        (**self).message(options)
+    }
+
+    fn flavor(&self, options: &DialogOptions) -> &'static str {
+        (**self).flavor(options)
+    }
+
+    fn watch_client(&mut self, fd: RawFd) {
+        (**self).watch_client(fd)
+    }
+
+    fn prepare(&mut self, options: &DialogOptions) -> Result<(), BackendError> {
+        (**self).prepare(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_passphrases_in_groups_of_five() {
+        assert_eq!(
+            format_passphrase("abcdefghijkl").expose(),
+            "abcde\u{2009}fghij\u{2009}kl"
+        );
+        assert_eq!(format_passphrase("äöüß€").expose(), "äöüß€");
+        assert_eq!(format_passphrase("").expose(), "");
+    }
+
+    #[test]
+    fn unformatting_restores_the_passphrase() {
+        let formatted = format_passphrase("correct horse");
+        assert_eq!(
+            unformat_passphrase(formatted.expose()).expose(),
+            "correct horse"
+        );
+        assert_eq!(
+            unformat_passphrase("abcde\u{2009}fghij\u{2009}kl").expose(),
+            "abcdefghijkl"
+        );
+    }
+
+    #[test]
+    fn thin_spaces_elsewhere_are_kept() {
+        for typed in [
+            "a\u{2009}b",
+            "abcde\u{2009}",
+            "\u{2009}\u{2009}\u{2009}\u{2009}\u{2009}\u{2009}",
+            "abcde\u{2009}fghij\u{2009}",
+        ] {
+            assert_eq!(unformat_passphrase(typed).expose(), typed);
+        }
+        // one that was formatted keeps its own
+        let formatted = format_passphrase("abcd\u{2009}efgh");
+        assert_eq!(
+            unformat_passphrase(formatted.expose()).expose(),
+            "abcd\u{2009}efgh"
+        );
+    }
+}
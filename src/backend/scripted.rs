@@ -0,0 +1,452 @@
+#[cfg(test)]
+use std::collections::HashMap;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Read},
+    os::fd::{FromRawFd, OwnedFd, RawFd},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use crate::secret::Secret;
+
+use super::{
+    BackendError, ConfirmOutcome, DialogOptions, MessageOutcome, PinOutcome, PinentryBackend,
+};
+
+/// Where the scripted backend takes the passphrase from. Only the first line
+/// is used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PinSource {
+    /// `env:NAME`
+    Env(String),
+    /// `fd:N`, a descriptor inherited from whoever started gpg-agent. It is
+    /// read once and closed, so only the first `GETPIN` gets an answer.
+    /// Stdin, stdout and stderr carry other things and are refused.
+    Fd(RawFd),
+    /// `file:PATH`
+    File(PathBuf),
+}
+
+impl Default for PinSource {
+    fn default() -> Self {
+        PinSource::Env("PINENTRY_PIN".to_string())
+    }
+}
+
+impl FromStr for PinSource {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.split_once(':') {
+            Some(("env", name)) if !name.is_empty() => Ok(PinSource::Env(name.to_string())),
+            Some(("fd", fd)) => match fd.parse() {
+                Ok(fd) if fd > libc::STDERR_FILENO => Ok(PinSource::Fd(fd)),
+                Ok(fd) => Err(format!("file descriptor {} is not ours to read", fd)),
+                Err(_) => Err(format!("invalid file descriptor `{}`", fd)),
+            },
+            Some(("file", path)) if !path.is_empty() => Ok(PinSource::File(path.into())),
+            _ => Err(format!(
+                "expected env:NAME, fd:N or file:PATH, got `{}`",
+                input
+            )),
+        }
+    }
+}
+
+/// How the scripted backend answers `CONFIRM`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum ConfirmPolicy {
+    AlwaysYes,
+    #[default]
+    AlwaysNo,
+    /// Answers by the key info of the request. Rules are checked in order,
+    /// a trailing `*` matches any rest, and no match means no.
+    PerKeyInfo(Vec<(String, bool)>),
+}
+
+impl ConfirmPolicy {
+    fn confirms(&self, key_info: Option<&str>) -> bool {
+        match self {
+            ConfirmPolicy::AlwaysYes => true,
+            ConfirmPolicy::AlwaysNo => false,
+            ConfirmPolicy::PerKeyInfo(rules) => rules
+                .iter()
+                .find(|(pattern, _)| match pattern.strip_suffix('*') {
+                    Some(prefix) => key_info.unwrap_or_default().starts_with(prefix),
+                    None => key_info == Some(pattern.as_str()),
+                })
+                .is_some_and(|(_, confirm)| *confirm),
+        }
+    }
+}
+
+impl FromStr for ConfirmPolicy {
+    type Err = String;
+
+    /// Takes `always-yes`, `always-no` or rules such as `n/1234=yes, *=no`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "always-yes" => return Ok(ConfirmPolicy::AlwaysYes),
+            "always-no" => return Ok(ConfirmPolicy::AlwaysNo),
+            _ => {}
+        }
+
+        input
+            .split(',')
+            .map(|rule| match rule.trim().split_once('=') {
+                Some((pattern, value)) if value.trim() == "yes" => {
+                    Ok((pattern.trim().to_string(), true))
+                }
+                Some((pattern, value)) if value.trim() == "no" => {
+                    Ok((pattern.trim().to_string(), false))
+                }
+                _ => Err(format!(
+                    "expected KEYINFO=yes or KEYINFO=no, got `{}`",
+                    rule.trim()
+                )),
+            })
+            .collect::<Result<_, _>>()
+            .map(ConfirmPolicy::PerKeyInfo)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScriptedOptions {
+    pub pin: PinSource,
+    pub confirm: ConfirmPolicy,
+}
+
+/// Answers without any user interaction, for CI and other unattended use.
+#[derive(Debug, Default)]
+pub struct ScriptedBackend {
+    options: ScriptedOptions,
+    /// Whether the descriptor of a [`PinSource::Fd`] has been taken, which
+    /// only happens once the backend is asked for a passphrase.
+    fd_taken: bool,
+    /// Stands in for the process environment when set.
+    #[cfg(test)]
+    env: Option<HashMap<String, String>>,
+}
+
+impl ScriptedBackend {
+    pub fn new(options: ScriptedOptions) -> Self {
+        ScriptedBackend {
+            options,
+            fd_taken: false,
+            #[cfg(test)]
+            env: None,
+        }
+    }
+
+    /// The file the passphrase is read from, if any, for the sandbox.
+    pub fn readable_paths(&self) -> Vec<PathBuf> {
+        match &self.options.pin {
+            PinSource::File(path) => vec![path.clone()],
+            PinSource::Env(_) | PinSource::Fd(_) => vec![],
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        #[cfg(test)]
+        if let Some(env) = &self.env {
+            return env.get(name).cloned();
+        }
+        env::var(name).ok()
+    }
+
+    fn read_pin(&mut self) -> io::Result<Secret> {
+        let contents = match &self.options.pin {
+            PinSource::Env(name) => self.var(name).map(Secret::from).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{} is not set", name))
+            })?,
+            PinSource::File(path) => fs::read_to_string(path).map(Secret::from)?,
+            PinSource::Fd(fd) => {
+                let owned = if self.fd_taken { None } else { inherit(*fd) };
+                self.fd_taken = true;
+                let Some(owned) = owned else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("file descriptor {} is closed or was already read", fd),
+                    ));
+                };
+                let mut contents = String::new();
+                File::from(owned).read_to_string(&mut contents)?;
+                Secret::from(contents)
+            }
+        };
+
+        Ok(Secret::from(
+            contents.expose().lines().next().unwrap_or_default(),
+        ))
+    }
+}
+
+/// Owns `fd` if it is open and none of stdin, stdout and stderr.
+fn inherit(fd: RawFd) -> Option<OwnedFd> {
+    if fd <= libc::STDERR_FILENO || unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return None;
+    }
+    // SAFETY: the descriptor is open, and it was handed to us for exactly
+    // this, so nothing else in the process uses it
+    Some(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+impl PinentryBackend for ScriptedBackend {
+    fn get_pin(
+        &mut self,
+        options: &DialogOptions,
+        _quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+        check_pin: &mut dyn FnMut(&str) -> io::Result<Option<String>>,
+    ) -> Result<PinOutcome, BackendError> {
+        // the agent only sets an error when it rejected the last answer, and
+        // the same answer would be rejected again
+        if options.error.is_some() {
+            return Ok(PinOutcome::Canceled);
+        }
+
+        let pin = self
+            .read_pin()
+            .map_err(|error| BackendError::Unavailable(format!("scripted: {}", error)))?;
+        // there is no other answer to give if the client rejects this one
+        if options.constraints_enforce
+            && check_pin(pin.expose()).map_err(BackendError::Io)?.is_some()
+        {
+            return Ok(PinOutcome::Canceled);
+        }
+
+        // asked twice, the answer would be the same
+        if options.repeat {
+            Ok(PinOutcome::Repeated(pin))
+        } else {
+            Ok(PinOutcome::Pin(pin))
+        }
+    }
+
+    fn confirm(&mut self, options: &DialogOptions) -> Result<ConfirmOutcome, BackendError> {
+        if self.options.confirm.confirms(options.key_info.as_deref()) {
+            Ok(ConfirmOutcome::Confirmed)
+        } else {
+            Ok(ConfirmOutcome::NotConfirmed)
+        }
+    }
+
+    fn message(&mut self, _options: &DialogOptions) -> Result<MessageOutcome, BackendError> {
+        Ok(MessageOutcome::Dismissed)
+    }
+
+    fn flavor(&self, _options: &DialogOptions) -> &'static str {
+        "scripted"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        os::{fd::IntoRawFd, unix::net::UnixStream},
+    };
+
+    use super::*;
+
+    fn get_pin(backend: &mut ScriptedBackend) -> Result<PinOutcome, BackendError> {
+        backend.get_pin(&DialogOptions::default(), &mut |_| Ok(None), &mut |_| {
+            Ok(None)
+        })
+    }
+
+    /// A backend that sees `vars` instead of the process environment.
+    fn with_env(options: ScriptedOptions, vars: &[(&str, &str)]) -> ScriptedBackend {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        ScriptedBackend {
+            env: Some(vars),
+            ..ScriptedBackend::new(options)
+        }
+    }
+
+    #[test]
+    fn parses_pin_sources() {
+        assert_eq!(
+            "env:CI_PIN".parse(),
+            Ok(PinSource::Env("CI_PIN".to_string()))
+        );
+        assert_eq!("fd:3".parse(), Ok(PinSource::Fd(3)));
+        assert_eq!(
+            "file:/run/secrets/pin".parse(),
+            Ok(PinSource::File("/run/secrets/pin".into()))
+        );
+        assert_eq!(
+            "fd:three".parse::<PinSource>(),
+            Err("invalid file descriptor `three`".to_string())
+        );
+        assert_eq!(
+            "fd:0".parse::<PinSource>(),
+            Err("file descriptor 0 is not ours to read".to_string())
+        );
+        assert!("stdin".parse::<PinSource>().is_err());
+    }
+
+    #[test]
+    fn reads_the_first_line_of_a_file() {
+        let path = env::temp_dir().join(format!("pinentry-scripted-{}", std::process::id()));
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(b"secret\nignored\n")
+            .unwrap();
+
+        let mut backend = ScriptedBackend::new(ScriptedOptions {
+            pin: PinSource::File(path.clone()),
+            ..Default::default()
+        });
+        let outcome = get_pin(&mut backend);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(outcome.unwrap(), PinOutcome::Pin("secret".into()));
+    }
+
+    #[test]
+    fn leaves_a_descriptor_alone_until_asked() {
+        let (reader, _writer) = UnixStream::pair().unwrap();
+        let fd = reader.into_raw_fd();
+
+        drop(ScriptedBackend::new(ScriptedOptions {
+            pin: PinSource::Fd(fd),
+            ..Default::default()
+        }));
+        assert_ne!(unsafe { libc::fcntl(fd, libc::F_GETFD) }, -1);
+        // SAFETY: still open, nothing took it
+        drop(unsafe { OwnedFd::from_raw_fd(fd) });
+    }
+
+    #[test]
+    fn reads_a_descriptor_only_once() {
+        let (reader, writer) = UnixStream::pair().unwrap();
+        (&writer).write_all(b"secret\n").unwrap();
+        drop(writer);
+
+        let mut backend = ScriptedBackend::new(ScriptedOptions {
+            pin: PinSource::Fd(reader.into_raw_fd()),
+            ..Default::default()
+        });
+        assert_eq!(
+            get_pin(&mut backend).unwrap(),
+            PinOutcome::Pin("secret".into())
+        );
+        assert!(matches!(
+            get_pin(&mut backend),
+            Err(BackendError::Unavailable(reason)) if reason.ends_with("is closed or was already read")
+        ));
+    }
+
+    #[test]
+    fn missing_pin_makes_the_backend_unavailable() {
+        let mut backend = with_env(
+            ScriptedOptions {
+                pin: PinSource::Env("PINENTRY_TEST_UNSET_PIN".to_string()),
+                ..Default::default()
+            },
+            &[],
+        );
+
+        assert!(matches!(
+            get_pin(&mut backend),
+            Err(BackendError::Unavailable(reason)) if reason == "scripted: PINENTRY_TEST_UNSET_PIN is not set"
+        ));
+    }
+
+    #[test]
+    fn rejected_pin_is_not_tried_again() {
+        let mut backend = ScriptedBackend::default();
+        let options = DialogOptions {
+            error: Some("Bad Passphrase (try 2 of 3)".to_string()),
+            ..Default::default()
+        };
+
+        let outcome = backend.get_pin(&options, &mut |_| Ok(None), &mut |_| Ok(None));
+        assert_eq!(outcome.unwrap(), PinOutcome::Canceled);
+    }
+
+    #[test]
+    fn pin_rejected_by_the_client_is_canceled() {
+        let mut backend = with_env(
+            ScriptedOptions {
+                pin: PinSource::Env("PIN".to_string()),
+                ..Default::default()
+            },
+            &[("PIN", "short")],
+        );
+        let options = DialogOptions {
+            constraints_enforce: true,
+            ..Default::default()
+        };
+
+        let outcome = backend.get_pin(&options, &mut |_| Ok(None), &mut |_| {
+            Ok(Some("Too short".to_string()))
+        });
+        assert_eq!(outcome.unwrap(), PinOutcome::Canceled);
+    }
+
+    #[test]
+    fn repeat_is_answered_with_the_same_pin() {
+        let mut backend = with_env(
+            ScriptedOptions {
+                pin: PinSource::Env("PIN".to_string()),
+                ..Default::default()
+            },
+            &[("PIN", "secret")],
+        );
+        let options = DialogOptions {
+            repeat: true,
+            ..Default::default()
+        };
+
+        let outcome = backend.get_pin(&options, &mut |_| Ok(None), &mut |_| Ok(None));
+        assert_eq!(outcome.unwrap(), PinOutcome::Repeated("secret".into()));
+    }
+
+    #[test]
+    fn confirms_by_policy() {
+        let policy: ConfirmPolicy = "n/1234 = yes, s/*=yes , *=no".parse().unwrap();
+
+        assert!(policy.confirms(Some("n/1234")));
+        assert!(policy.confirms(Some("s/5678")));
+        assert!(!policy.confirms(Some("n/5678")));
+        assert!(!policy.confirms(None));
+
+        assert!(ConfirmPolicy::AlwaysYes.confirms(None));
+        assert!(!ConfirmPolicy::AlwaysNo.confirms(Some("n/1234")));
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        assert_eq!(
+            "n/1234=maybe".parse::<ConfirmPolicy>(),
+            Err("expected KEYINFO=yes or KEYINFO=no, got `n/1234=maybe`".to_string())
+        );
+    }
+
+    #[test]
+    fn confirm_answers_from_the_policy() {
+        let mut backend = ScriptedBackend::new(ScriptedOptions {
+            confirm: "n/1234=yes".parse().unwrap(),
+            ..Default::default()
+        });
+        let options = |key_info: &str| DialogOptions {
+            key_info: Some(key_info.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            backend.confirm(&options("n/1234")).unwrap(),
+            ConfirmOutcome::Confirmed
+        );
+        assert_eq!(
+            backend.confirm(&options("n/5678")).unwrap(),
+            ConfirmOutcome::NotConfirmed
+        );
+    }
+}
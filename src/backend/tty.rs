@@ -0,0 +1,782 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    mem,
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::fs::OpenOptionsExt,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    secret::{self, Secret},
+    strength,
+};
+
+use super::{
+    BackendError, ConfirmOutcome, DialogOptions, MessageOutcome, PinOutcome, PinentryBackend,
+};
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+const SAVE_CURSOR: &str = "\x1b7";
+const RESTORE_CURSOR: &str = "\x1b8";
+
+/// Asks on the terminal named by `OPTION ttyname`. Stdin and stdout carry the
+/// Assuan stream, so the terminal is opened separately for every dialog.
+#[derive(Debug, Default)]
+pub struct TtyBackend {
+    /// The client's input, see [`PinentryBackend::watch_client`].
+    pub client: Option<RawFd>,
+}
+
+impl PinentryBackend for TtyBackend {
+    fn get_pin(
+        &mut self,
+        options: &DialogOptions,
+        quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+        check_pin: &mut dyn FnMut(&str) -> io::Result<Option<String>>,
+    ) -> Result<PinOutcome, BackendError> {
+        let mut terminal = Terminal::open(options, self.client).map_err(unavailable)?;
+        get_pin(&mut terminal, options, quality, check_pin).map_err(BackendError::Io)
+    }
+
+    fn confirm(&mut self, options: &DialogOptions) -> Result<ConfirmOutcome, BackendError> {
+        let mut terminal = Terminal::open(options, self.client).map_err(unavailable)?;
+        confirm(&mut terminal, options).map_err(BackendError::Io)
+    }
+
+    fn message(&mut self, options: &DialogOptions) -> Result<MessageOutcome, BackendError> {
+        let mut terminal = Terminal::open(options, self.client).map_err(unavailable)?;
+        message(&mut terminal, options).map_err(BackendError::Io)
+    }
+
+    fn flavor(&self, _options: &DialogOptions) -> &'static str {
+        "tty"
+    }
+
+    fn watch_client(&mut self, fd: RawFd) {
+        self.client = Some(fd);
+    }
+
+    /// The terminal is opened again for the dialog, this only checks that it
+    /// can be.
+    fn prepare(&mut self, options: &DialogOptions) -> Result<(), BackendError> {
+        Terminal::open(options, self.client).map_err(unavailable)?;
+        Ok(())
+    }
+}
+
+/// A terminal that cannot be opened is not the client's fault, the dialog
+/// just could not be shown.
+fn unavailable(error: io::Error) -> BackendError {
+    BackendError::Unavailable(format!("terminal: {}", error))
+}
+
+fn get_pin(
+    terminal: &mut Terminal,
+    options: &DialogOptions,
+    quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+    check_pin: &mut dyn FnMut(&str) -> io::Result<Option<String>>,
+) -> io::Result<PinOutcome> {
+    terminal.draw(options)?;
+    // there is no tooltip to hold the long hint
+    if let Some(hint) = options
+        .constraints_hint_long
+        .as_ref()
+        .or(options.constraints_hint_short.as_ref())
+    {
+        writeln!(terminal.tty, "{}\n", hint)?;
+    }
+
+    loop {
+        let pin = match terminal.read_hidden(options.prompt.as_deref().unwrap_or("PIN:"))? {
+            Input::Line(pin) => pin,
+            Input::Eof => return Ok(PinOutcome::Canceled),
+            Input::TimedOut => return Ok(PinOutcome::TimedOut),
+        };
+        if options.quality_bar && !pin.is_empty() {
+            terminal.write_quality(quality, pin.expose())?;
+        }
+        let repeated = match options.repeat {
+            false => false,
+            true => {
+                match terminal.read_hidden(options.repeat_prompt.as_deref().unwrap_or("Repeat:"))? {
+                    Input::Line(repeated) if repeated == pin => {
+                        if let Some(ok) = &options.repeat_ok {
+                            writeln!(terminal.tty, "{}", ok)?;
+                        }
+                        true
+                    }
+                    Input::Line(_) => {
+                        terminal.write_error(
+                            options
+                                .repeat_error
+                                .as_deref()
+                                .unwrap_or("Passphrases do not match"),
+                        )?;
+                        continue;
+                    }
+                    Input::Eof => return Ok(PinOutcome::Canceled),
+                    Input::TimedOut => return Ok(PinOutcome::TimedOut),
+                }
+            }
+        };
+
+        if options.constraints_enforce {
+            if let Some(error) = check_pin(pin.expose())? {
+                terminal.write_error(&options.constraints_error(&error))?;
+                continue;
+            }
+        }
+        return Ok(if repeated {
+            PinOutcome::Repeated(pin)
+        } else {
+            PinOutcome::Pin(pin)
+        });
+    }
+}
+
+fn confirm(terminal: &mut Terminal, options: &DialogOptions) -> io::Result<ConfirmOutcome> {
+    terminal.draw(options)?;
+
+    let mut choices = vec![(
+        options.ok_button.as_deref().unwrap_or("_OK"),
+        ConfirmOutcome::Confirmed,
+    )];
+    if let Some(not_ok) = &options.not_ok_button {
+        choices.push((not_ok, ConfirmOutcome::NotConfirmed));
+    }
+    choices.push((
+        options.cancel_button.as_deref().unwrap_or("_Cancel"),
+        ConfirmOutcome::Canceled,
+    ));
+    let mut choices: Vec<_> = choices
+        .into_iter()
+        .map(|(label, outcome)| {
+            let (label, key) = mnemonic(label);
+            (label, key, outcome)
+        })
+        .collect();
+
+    let question = choices
+        .iter()
+        .map(|(label, key, _)| format!("{} [{}]", label, key))
+        .collect::<Vec<_>>()
+        .join(" / ");
+
+    loop {
+        let answer = match terminal.ask(&format!("{}:", question), false)? {
+            Input::Line(answer) => answer,
+            Input::Eof => return Ok(ConfirmOutcome::Canceled),
+            Input::TimedOut => return Ok(ConfirmOutcome::TimedOut),
+        };
+
+        let key = answer
+            .expose()
+            .trim()
+            .chars()
+            .next()
+            .map(|key| key.to_ascii_lowercase());
+        if let Some(position) = choices.iter().position(|(_, k, _)| Some(*k) == key) {
+            return Ok(choices.swap_remove(position).2);
+        }
+    }
+}
+
+fn message(terminal: &mut Terminal, options: &DialogOptions) -> io::Result<MessageOutcome> {
+    terminal.draw(options)?;
+
+    let (ok, _) = mnemonic(options.ok_button.as_deref().unwrap_or("_OK"));
+    Ok(match terminal.ask(&format!("{} [Enter]", ok), false)? {
+        Input::TimedOut => MessageOutcome::TimedOut,
+        Input::Line(_) | Input::Eof => MessageOutcome::Dismissed,
+    })
+}
+
+enum Input {
+    Line(Secret),
+    Eof,
+    TimedOut,
+}
+
+enum Wait {
+    Readable,
+    /// The countdown has to be updated.
+    Tick,
+    TimedOut,
+}
+
+/// What a terminal can do, going by the terminfo name in `OPTION ttytype`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Capabilities {
+    /// Plain text and carriage returns only.
+    Dumb,
+    /// Bold text and a cursor that can be saved, but no colors.
+    Monochrome,
+    Color,
+}
+
+impl Capabilities {
+    fn from_tty_type(tty_type: Option<&str>) -> Self {
+        match tty_type {
+            None | Some("" | "dumb" | "unknown" | "emacs") => Capabilities::Dumb,
+            Some(name) if name.ends_with("-mono") || name.ends_with("-m") => {
+                Capabilities::Monochrome
+            }
+            // vt100, vt220 and friends, but not vte
+            Some(name)
+                if name.starts_with("vt")
+                    && name[2..].starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                Capabilities::Monochrome
+            }
+            Some(_) => Capabilities::Color,
+        }
+    }
+
+    /// The sequences that start bold text and errors, and the one that ends
+    /// both.
+    fn styles(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Capabilities::Dumb => ("", "", ""),
+            Capabilities::Monochrome => (BOLD, BOLD, RESET),
+            Capabilities::Color => (BOLD, RED, RESET),
+        }
+    }
+}
+
+struct Terminal {
+    tty: File,
+    capabilities: Capabilities,
+    deadline: Option<Instant>,
+    client: Option<RawFd>,
+}
+
+impl Terminal {
+    fn open(options: &DialogOptions, client: Option<RawFd>) -> io::Result<Terminal> {
+        let Some(tty_name) = &options.tty_name else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no ttyname was set",
+            ));
+        };
+
+        // O_NOCTTY: never become the controlling terminal of the client's tty
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(tty_name)?;
+        if unsafe { libc::isatty(tty.as_raw_fd()) } != 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a terminal", tty_name),
+            ));
+        }
+
+        Ok(Terminal {
+            tty,
+            capabilities: Capabilities::from_tty_type(options.tty_type.as_deref()),
+            deadline: options
+                .timeout
+                .map(|seconds| Instant::now() + Duration::from_secs(seconds.into())),
+            client,
+        })
+    }
+
+    fn draw(&mut self, options: &DialogOptions) -> io::Result<()> {
+        let mut screen = Vec::new();
+        render(&mut screen, options, self.capabilities)?;
+        self.tty.write_all(&screen)
+    }
+
+    fn write_error(&mut self, error: &str) -> io::Result<()> {
+        let (_, error_style, reset) = self.capabilities.styles();
+        writeln!(self.tty, "{}{}{}", error_style, error, reset)
+    }
+
+    /// There is no bar to update while typing, so the score is shown once the
+    /// passphrase has been entered.
+    fn write_quality(
+        &mut self,
+        quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+        passphrase: &str,
+    ) -> io::Result<()> {
+        let (score, explanation) = match quality(passphrase)? {
+            Some(score) => (score, None),
+            None => {
+                let strength = strength::estimate(passphrase);
+                (strength.score, strength.explanation())
+            }
+        };
+
+        match explanation {
+            Some(explanation) => writeln!(self.tty, "Quality: {}% ({})", score, explanation),
+            None => writeln!(self.tty, "Quality: {}%", score),
+        }
+    }
+
+    /// Asks for a line after `prompt` without echoing it.
+    fn read_hidden(&self, prompt: &str) -> io::Result<Input> {
+        let (prompt, _) = mnemonic(prompt);
+        let input = {
+            let _echo_off = self.hide_input()?;
+            self.ask(&prompt, true)?
+        };
+        // the newline was not echoed either
+        writeln!(&self.tty)?;
+        Ok(input)
+    }
+
+    /// Asks for a line after `prompt`, counting down the seconds that are
+    /// left to answer.
+    fn ask(&self, prompt: &str, hidden: bool) -> io::Result<Input> {
+        self.write_prompt(prompt)?;
+        self.read_line(prompt, hidden)
+    }
+
+    fn write_prompt(&self, prompt: &str) -> io::Result<()> {
+        let mut tty = &self.tty;
+        match self.deadline {
+            Some(deadline) => {
+                let (seconds, _) = countdown(deadline.saturating_duration_since(Instant::now()));
+                write!(tty, "[{:>3}s] {} ", seconds, prompt)
+            }
+            None => write!(tty, "{} ", prompt),
+        }
+    }
+
+    /// Updates the countdown in front of `prompt`. Echoed input only survives
+    /// this where the cursor can be saved, so elsewhere the prompt is just
+    /// redrawn while nothing is echoed.
+    fn redraw_prompt(&self, prompt: &str, hidden: bool) -> io::Result<()> {
+        let mut tty = &self.tty;
+        if self.capabilities != Capabilities::Dumb {
+            write!(tty, "{}\r", SAVE_CURSOR)?;
+            self.write_prompt(prompt)?;
+            write!(tty, "{}", RESTORE_CURSOR)
+        } else if hidden {
+            write!(tty, "\r")?;
+            self.write_prompt(prompt)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Turns echo off until the returned guard is dropped.
+    fn hide_input(&self) -> io::Result<EchoOff<'_>> {
+        let fd = self.tty.as_raw_fd();
+        let mut termios: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let saved = termios;
+        termios.c_lflag &= !libc::ECHO;
+        if unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(EchoOff {
+            tty: &self.tty,
+            saved,
+        })
+    }
+
+    /// Reads one line in canonical mode, without its newline. `prompt` is
+    /// the one it was asked for with.
+    fn read_line(&self, prompt: &str, hidden: bool) -> io::Result<Input> {
+        let mut line = Secret::new();
+        // canonical mode hands out whole lines, up to the size of the
+        // kernel's line buffer
+        let mut buffer = [0; 4096];
+        let result = loop {
+            match self.wait() {
+                Ok(Wait::Readable) => {}
+                Ok(Wait::Tick) => match self.redraw_prompt(prompt, hidden) {
+                    Ok(()) => continue,
+                    Err(error) => break Err(error),
+                },
+                Ok(Wait::TimedOut) => break Ok(Input::TimedOut),
+                Err(error) => break Err(error),
+            }
+
+            let length = match (&self.tty).read(&mut buffer) {
+                Ok(length) => length,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => break Err(error),
+            };
+
+            // an empty read is ^D at the start of a line
+            if length == 0 && line.is_empty() {
+                break Ok(Input::Eof);
+            }
+
+            let chunk = &buffer[..length];
+            let (chunk, complete) = match chunk.strip_suffix(b"\n") {
+                Some(chunk) => (chunk, true),
+                None => (chunk, length == 0),
+            };
+            match std::str::from_utf8(chunk) {
+                Ok(chunk) => line.push_str(chunk),
+                Err(error) => break Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+            }
+
+            if complete {
+                break Ok(Input::Line(line));
+            }
+        };
+
+        secret::wipe(&mut buffer);
+        result
+    }
+
+    /// Waits until there is input, the countdown goes down or the deadline
+    /// has passed. Fails with an unexpected EOF if the client hangs up first.
+    fn wait(&self) -> io::Result<Wait> {
+        loop {
+            let timeout = match self.deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(Wait::TimedOut);
+                    }
+                    let (_, tick) = countdown(remaining);
+                    tick.try_into().unwrap_or(i32::MAX)
+                }
+                None => -1,
+            };
+
+            let mut poll_fds = [
+                libc::pollfd {
+                    fd: self.tty.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                // hangups are reported without asking, and a negative fd is
+                // skipped
+                libc::pollfd {
+                    fd: self.client.unwrap_or(-1),
+                    events: 0,
+                    revents: 0,
+                },
+            ];
+            match unsafe { libc::poll(poll_fds.as_mut_ptr(), 2, timeout) } {
+                -1 => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+                0 => {
+                    // the last tick is the deadline itself
+                    let passed = self
+                        .deadline
+                        .is_some_and(|deadline| deadline <= Instant::now());
+                    return Ok(if passed { Wait::TimedOut } else { Wait::Tick });
+                }
+                _ if poll_fds[1].revents & (libc::POLLHUP | libc::POLLERR) != 0 => {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                _ => return Ok(Wait::Readable),
+            }
+        }
+    }
+}
+
+struct EchoOff<'a> {
+    tty: &'a File,
+    saved: libc::termios,
+}
+
+impl Drop for EchoOff<'_> {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSAFLUSH, &self.saved) };
+    }
+}
+
+/// The whole seconds left to answer, and the milliseconds until that number
+/// goes down.
+fn countdown(remaining: Duration) -> (u128, u128) {
+    let millis = remaining.as_nanos().div_ceil(1_000_000);
+    (millis.div_ceil(1000), (millis.max(1) - 1) % 1000 + 1)
+}
+
+fn render(
+    screen: &mut impl Write,
+    options: &DialogOptions,
+    capabilities: Capabilities,
+) -> io::Result<()> {
+    let (bold, red, reset) = capabilities.styles();
+
+    writeln!(screen)?;
+    if let Some(title) = &options.title {
+        writeln!(screen, "{}{}{}", bold, title, reset)?;
+    }
+    if let Some(description) = &options.description {
+        writeln!(screen, "{}", description)?;
+    }
+    if let Some(error) = &options.error {
+        writeln!(screen, "{}{}{}", red, error, reset)?;
+    }
+    writeln!(screen)
+}
+
+/// Strips the `_` that marks a mnemonic from `label` and returns the label
+/// with its key. Labels without one use their first character.
+fn mnemonic(label: &str) -> (String, char) {
+    let mut text = String::new();
+    let mut key = None;
+    let mut chars = label.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('_', Some('_')) => {
+                chars.next();
+                text.push('_');
+            }
+            ('_', Some(next)) => {
+                key.get_or_insert(next.to_ascii_lowercase());
+            }
+            _ => text.push(c),
+        }
+    }
+
+    let key = key
+        .or_else(|| text.chars().next().map(|c| c.to_ascii_lowercase()))
+        .unwrap_or(' ');
+    (text, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::CStr,
+        io::BufReader,
+        os::{fd::FromRawFd, unix::net::UnixStream},
+        thread,
+    };
+
+    use super::*;
+    use crate::Pinentry;
+
+    /// Opens a pseudo-terminal and returns its controlling side with the name
+    /// of the terminal.
+    fn pty() -> (File, String) {
+        unsafe {
+            let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(master >= 0);
+            assert_eq!(libc::grantpt(master), 0);
+            assert_eq!(libc::unlockpt(master), 0);
+            let mut name = [0; 64];
+            assert_eq!(libc::ptsname_r(master, name.as_mut_ptr(), name.len()), 0);
+            let name = CStr::from_ptr(name.as_ptr()).to_str().unwrap().to_string();
+            (File::from_raw_fd(master), name)
+        }
+    }
+
+    /// Calls `action` with the terminal once `prompt` has been shown. The
+    /// terminal stays open until the thread is joined.
+    fn when_shown(
+        mut master: File,
+        prompt: &'static str,
+        action: impl FnOnce(&mut File) + Send + 'static,
+    ) -> thread::JoinHandle<File> {
+        thread::spawn(move || {
+            let mut screen = Vec::new();
+            let mut buffer = [0; 256];
+            while !String::from_utf8_lossy(&screen).contains(prompt) {
+                let length = master.read(&mut buffer).unwrap();
+                screen.extend_from_slice(&buffer[..length]);
+            }
+            action(&mut master);
+            master
+        })
+    }
+
+    #[test]
+    fn mnemonic_strips_the_marker() {
+        assert_eq!(mnemonic("_OK"), ("OK".to_string(), 'o'));
+        assert_eq!(mnemonic("Do _not"), ("Do not".to_string(), 'n'));
+        assert_eq!(mnemonic("snake__case"), ("snake_case".to_string(), 's'));
+        assert_eq!(mnemonic("Cancel"), ("Cancel".to_string(), 'c'));
+    }
+
+    #[test]
+    fn render_styles_known_terminals_only() {
+        let options = DialogOptions {
+            title: Some("Unlock".to_string()),
+            description: Some("Enter passphrase".to_string()),
+            error: Some("Bad passphrase".to_string()),
+            ..Default::default()
+        };
+
+        let mut plain = Vec::new();
+        render(&mut plain, &options, Capabilities::Dumb).unwrap();
+        assert_eq!(
+            String::from_utf8(plain).unwrap(),
+            "\nUnlock\nEnter passphrase\nBad passphrase\n\n"
+        );
+
+        let mut styled = Vec::new();
+        render(&mut styled, &options, Capabilities::Color).unwrap();
+        assert_eq!(
+            String::from_utf8(styled).unwrap(),
+            "\n\x1b[1mUnlock\x1b[0m\nEnter passphrase\n\x1b[31mBad passphrase\x1b[0m\n\n"
+        );
+
+        let mut mono = Vec::new();
+        render(&mut mono, &options, Capabilities::Monochrome).unwrap();
+        assert_eq!(
+            String::from_utf8(mono).unwrap(),
+            "\n\x1b[1mUnlock\x1b[0m\nEnter passphrase\n\x1b[1mBad passphrase\x1b[0m\n\n"
+        );
+    }
+
+    #[test]
+    fn capabilities_follow_the_terminal_type() {
+        let capabilities = Capabilities::from_tty_type;
+
+        assert_eq!(capabilities(None), Capabilities::Dumb);
+        assert_eq!(capabilities(Some("dumb")), Capabilities::Dumb);
+        assert_eq!(capabilities(Some("vt100")), Capabilities::Monochrome);
+        assert_eq!(capabilities(Some("xterm-mono")), Capabilities::Monochrome);
+        assert_eq!(capabilities(Some("vte-256color")), Capabilities::Color);
+        assert_eq!(capabilities(Some("xterm-256color")), Capabilities::Color);
+    }
+
+    #[test]
+    fn countdown_rounds_up_to_whole_seconds() {
+        assert_eq!(countdown(Duration::from_secs(10)), (10, 1000));
+        assert_eq!(countdown(Duration::from_millis(9500)), (10, 500));
+        assert_eq!(countdown(Duration::from_millis(1001)), (2, 1));
+        assert_eq!(countdown(Duration::from_micros(200)), (1, 1));
+    }
+
+    #[test]
+    fn missing_ttyname_makes_the_backend_unavailable() {
+        let result = TtyBackend::default().confirm(&DialogOptions::default());
+        assert!(matches!(result, Err(BackendError::Unavailable(_))));
+    }
+
+    #[test]
+    fn quality_errors_are_not_mistaken_for_a_missing_terminal() {
+        let (master, tty_name) = pty();
+        let typist = when_shown(master, "PIN:", |master| master.write_all(b"abc\n").unwrap());
+        let options = DialogOptions {
+            tty_name: Some(tty_name),
+            quality_bar: true,
+            ..Default::default()
+        };
+
+        let result = TtyBackend::default().get_pin(
+            &options,
+            &mut |_| Err(io::ErrorKind::BrokenPipe.into()),
+            &mut |_| Ok(None),
+        );
+        assert!(
+            matches!(result, Err(BackendError::Io(error)) if error.kind() == io::ErrorKind::BrokenPipe)
+        );
+        typist.join().unwrap();
+    }
+
+    #[test]
+    fn quality_shows_the_agents_score() {
+        let (master, tty_name) = pty();
+        let typist = when_shown(master, "PIN:", |master| master.write_all(b"abc\n").unwrap());
+        let options = DialogOptions {
+            tty_name: Some(tty_name),
+            quality_bar: true,
+            ..Default::default()
+        };
+
+        let mut rated = vec![];
+        let result = TtyBackend::default().get_pin(
+            &options,
+            &mut |pin| {
+                rated.push(pin.to_string());
+                Ok(Some(42))
+            },
+            &mut |_| Ok(None),
+        );
+        assert_eq!(result.unwrap(), PinOutcome::Pin("abc".into()));
+        assert_eq!(rated, vec!["abc"]);
+
+        let mut master = typist.join().unwrap();
+        let mut screen = Vec::new();
+        let mut buffer = [0; 256];
+        while !String::from_utf8_lossy(&screen).contains("Quality: 42%\r\n") {
+            let length = master.read(&mut buffer).unwrap();
+            screen.extend_from_slice(&buffer[..length]);
+        }
+    }
+
+    #[test]
+    fn getpin_asks_again_while_the_client_rejects_the_pin() {
+        let (master, tty_name) = pty();
+        let typist = when_shown(master, "PIN:", |master| {
+            master.write_all(b"short\n").unwrap();
+            // input typed before the prompt would be flushed
+            let mut screen = Vec::new();
+            let mut buffer = [0; 256];
+            while !String::from_utf8_lossy(&screen).contains("Too short\r\nPIN:") {
+                let length = master.read(&mut buffer).unwrap();
+                screen.extend_from_slice(&buffer[..length]);
+            }
+            master.write_all(b"long enough\n").unwrap();
+        });
+        let options = DialogOptions {
+            tty_name: Some(tty_name),
+            constraints_enforce: true,
+            ..Default::default()
+        };
+
+        let mut checked = vec![];
+        let result = TtyBackend::default().get_pin(&options, &mut |_| Ok(None), &mut |pin| {
+            checked.push(pin.to_string());
+            Ok((pin == "short").then(|| "Too short".to_string()))
+        });
+        assert_eq!(result.unwrap(), PinOutcome::Pin("long enough".into()));
+        assert_eq!(checked, vec!["short", "long enough"]);
+        typist.join().unwrap();
+    }
+
+    #[test]
+    fn getpin_is_closed_when_the_client_hangs_up() {
+        let (master, tty_name) = pty();
+        let (input, mut client) = UnixStream::pair().unwrap();
+        write!(
+            client,
+            "OPTION ttyname={}\nSETTIMEOUT 10\nGETPIN\n",
+            tty_name
+        )
+        .unwrap();
+        let terminal = when_shown(master, "PIN:", move |_| drop(client));
+
+        let mut backend = TtyBackend::default();
+        backend.watch_client(input.as_raw_fd());
+        let mut pinentry = Pinentry::with_backend(BufReader::new(&input), Vec::new(), backend);
+        pinentry.run().unwrap();
+
+        // closed well before the timeout, and nobody is left to answer
+        assert_eq!(
+            String::from_utf8(pinentry.writer).unwrap(),
+            "OK Pleased to meet you\nOK\nOK\n"
+        );
+        assert!(pinentry.session.is_closed());
+        terminal.join().unwrap();
+    }
+
+    #[test]
+    fn non_terminal_ttyname_makes_the_backend_unavailable() {
+        let options = DialogOptions {
+            tty_name: Some("/dev/null".to_string()),
+            ..Default::default()
+        };
+
+        let result = TtyBackend::default().get_pin(&options, &mut |_| Ok(None), &mut |_| Ok(None));
+        assert!(matches!(result, Err(BackendError::Unavailable(_))));
+    }
+}
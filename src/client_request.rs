@@ -1,4 +1,4 @@
-use crate::option::PinentryOption;
+use crate::{error::AssuanError, option::PinentryOption, percent};
 
 #[derive(Debug, PartialEq)]
 pub enum ClientRequest {
@@ -11,9 +11,11 @@ pub enum ClientRequest {
     Cancel,
     Auth,
     Nop,
+    GetInfo(String),
 
     GetPin,
     Confirm,
+    ConfirmOneButton,
     Message,
 
     SetTimeout(i32),
@@ -24,7 +26,11 @@ pub enum ClientRequest {
     SetCancel(String),
     SetNotOk(String),
     SetError(String),
-    SetRepeat,
+    /// Asks for the passphrase twice, optionally with a label for the second
+    /// entry.
+    SetRepeat(Option<String>),
+    SetRepeatError(String),
+    SetRepeatOk(String),
     SetQualityBar,
     SetQualityBarTooltip(String),
     SetGenPin,
@@ -33,72 +39,89 @@ pub enum ClientRequest {
 }
 
 impl ClientRequest {
-    pub fn parse(input: &str) -> Option<ClientRequest> {
-        let parts: Vec<&str> = input.split_whitespace().collect();
-
-        match parts[0] {
-            "BYE" => Some(ClientRequest::Bye),
-            "RESET" => Some(ClientRequest::Reset),
-            "END" => Some(ClientRequest::End),
-            "HELP" => Some(ClientRequest::Help),
-            "QUIT" => Some(ClientRequest::Quit),
-            "CANCEL" => Some(ClientRequest::Cancel),
-            "AUTH" => Some(ClientRequest::Auth),
-            "NOP" => Some(ClientRequest::Nop),
-            "OPTION" => Some(ClientRequest::Option(PinentryOption::parse(
-                parts.get(1).unwrap_or(&""),
-            ))),
-            "GETPIN" => Some(ClientRequest::GetPin),
-            "CONFIRM" => Some(ClientRequest::Confirm),
-            "MESSAGE" => Some(ClientRequest::Message),
+    pub fn parse(input: &str) -> Result<ClientRequest, AssuanError> {
+        // only the first space separates the command from its argument, anything
+        // after it is kept verbatim
+        let (command, argument) = input.split_once(' ').unwrap_or((input, ""));
+
+        match command {
+            "BYE" => Ok(ClientRequest::Bye),
+            "RESET" => Ok(ClientRequest::Reset),
+            "END" => Ok(ClientRequest::End),
+            "HELP" => Ok(ClientRequest::Help),
+            "QUIT" => Ok(ClientRequest::Quit),
+            "CANCEL" => Ok(ClientRequest::Cancel),
+            "AUTH" => Ok(ClientRequest::Auth),
+            "NOP" => Ok(ClientRequest::Nop),
+            "GETINFO" => Ok(ClientRequest::GetInfo(argument.trim().to_string())),
+            "OPTION" => Ok(ClientRequest::Option(PinentryOption::parse(argument)?)),
+            "GETPIN" => Ok(ClientRequest::GetPin),
+            "CONFIRM" => match argument.trim() {
+                "--one-button" => Ok(ClientRequest::ConfirmOneButton),
+                _ => Ok(ClientRequest::Confirm),
+            },
+            "MESSAGE" => Ok(ClientRequest::Message),
             "SETTIMEOUT" => {
-                let timeout = parts.get(1).unwrap_or(&"0").parse().unwrap_or(0);
-                Some(ClientRequest::SetTimeout(timeout))
+                let timeout = argument.trim().parse().unwrap_or(0);
+                Ok(ClientRequest::SetTimeout(timeout))
             }
             "SETDESC" => {
-                let desc = parts[1..].join(" ");
-                Some(ClientRequest::SetDescription(desc))
+                let desc = percent::decode(argument)?;
+                Ok(ClientRequest::SetDescription(desc))
             }
             "SETPROMPT" => {
-                let prompt = parts[1..].join(" ");
-                Some(ClientRequest::SetPrompt(prompt))
+                let prompt = percent::decode(argument)?;
+                Ok(ClientRequest::SetPrompt(prompt))
             }
             "SETTITLE" => {
-                let title = parts[1..].join(" ");
-                Some(ClientRequest::SetTitle(title))
+                let title = percent::decode(argument)?;
+                Ok(ClientRequest::SetTitle(title))
             }
             "SETOK" => {
-                let ok = parts[1..].join(" ");
-                Some(ClientRequest::SetOk(ok))
+                let ok = percent::decode(argument)?;
+                Ok(ClientRequest::SetOk(ok))
             }
             "SETCANCEL" => {
-                let cancel = parts[1..].join(" ");
-                Some(ClientRequest::SetCancel(cancel))
+                let cancel = percent::decode(argument)?;
+                Ok(ClientRequest::SetCancel(cancel))
             }
             "SETNOTOK" => {
-                let notok = parts[1..].join(" ");
-                Some(ClientRequest::SetNotOk(notok))
+                let notok = percent::decode(argument)?;
+                Ok(ClientRequest::SetNotOk(notok))
             }
             "SETERROR" => {
-                let error = parts[1..].join(" ");
-                Some(ClientRequest::SetError(error))
+                let error = percent::decode(argument)?;
+                Ok(ClientRequest::SetError(error))
             }
-            "SETREPEAT" => Some(ClientRequest::SetRepeat),
-            "SETQUALITYBAR" => Some(ClientRequest::SetQualityBar),
+            "SETREPEAT" => {
+                let prompt = percent::decode(argument)?;
+                Ok(ClientRequest::SetRepeat(
+                    Some(prompt).filter(|prompt| !prompt.is_empty()),
+                ))
+            }
+            "SETREPEATERROR" => {
+                let error = percent::decode(argument)?;
+                Ok(ClientRequest::SetRepeatError(error))
+            }
+            "SETREPEATOK" => {
+                let ok = percent::decode(argument)?;
+                Ok(ClientRequest::SetRepeatOk(ok))
+            }
+            "SETQUALITYBAR" => Ok(ClientRequest::SetQualityBar),
             "SETQUALITYBARTOOLTIP" => {
-                let tooltip = parts[1..].join(" ");
-                Some(ClientRequest::SetQualityBarTooltip(tooltip))
+                let tooltip = percent::decode(argument)?;
+                Ok(ClientRequest::SetQualityBarTooltip(tooltip))
             }
-            "SETGENPIN" => Some(ClientRequest::SetGenPin),
+            "SETGENPIN" => Ok(ClientRequest::SetGenPin),
             "SETGENPINTOOLTIP" => {
-                let tooltip = parts[1..].join(" ");
-                Some(ClientRequest::SetGenPinTooltip(tooltip))
+                let tooltip = percent::decode(argument)?;
+                Ok(ClientRequest::SetGenPinTooltip(tooltip))
             }
             "SETKEYINFO" => {
-                let keyinfo = parts[1..].join(" ");
-                Some(ClientRequest::SetKeyInfo(keyinfo))
+                let keyinfo = percent::decode(argument)?;
+                Ok(ClientRequest::SetKeyInfo(keyinfo))
             }
-            _ => None,
+            _ => Err(AssuanError::UnknownIPCCommand),
         }
     }
 }
@@ -109,87 +132,101 @@ mod tests {
 
     #[test]
     fn parses_bye_command() {
-        assert_eq!(ClientRequest::parse("BYE"), Some(ClientRequest::Bye));
+        assert_eq!(ClientRequest::parse("BYE"), Ok(ClientRequest::Bye));
     }
 
     #[test]
     fn parses_reset_command() {
-        assert_eq!(ClientRequest::parse("RESET"), Some(ClientRequest::Reset));
+        assert_eq!(ClientRequest::parse("RESET"), Ok(ClientRequest::Reset));
     }
 
     #[test]
     fn parses_end_command() {
-        assert_eq!(ClientRequest::parse("END"), Some(ClientRequest::End));
+        assert_eq!(ClientRequest::parse("END"), Ok(ClientRequest::End));
     }
 
     #[test]
     fn parses_help_command() {
-        assert_eq!(ClientRequest::parse("HELP"), Some(ClientRequest::Help));
+        assert_eq!(ClientRequest::parse("HELP"), Ok(ClientRequest::Help));
     }
 
     #[test]
     fn parses_quit_command() {
-        assert_eq!(ClientRequest::parse("QUIT"), Some(ClientRequest::Quit));
+        assert_eq!(ClientRequest::parse("QUIT"), Ok(ClientRequest::Quit));
     }
 
     #[test]
     fn parses_cancel_command() {
-        assert_eq!(ClientRequest::parse("CANCEL"), Some(ClientRequest::Cancel));
+        assert_eq!(ClientRequest::parse("CANCEL"), Ok(ClientRequest::Cancel));
     }
 
     #[test]
     fn parses_auth_command() {
-        assert_eq!(ClientRequest::parse("AUTH"), Some(ClientRequest::Auth));
+        assert_eq!(ClientRequest::parse("AUTH"), Ok(ClientRequest::Auth));
     }
 
     #[test]
     fn parses_nop_command() {
-        assert_eq!(ClientRequest::parse("NOP"), Some(ClientRequest::Nop));
+        assert_eq!(ClientRequest::parse("NOP"), Ok(ClientRequest::Nop));
+    }
+
+    #[test]
+    fn parses_getinfo_command() {
+        assert_eq!(
+            ClientRequest::parse("GETINFO version"),
+            Ok(ClientRequest::GetInfo("version".to_string()))
+        );
+        assert_eq!(
+            ClientRequest::parse("GETINFO"),
+            Ok(ClientRequest::GetInfo("".to_string()))
+        );
     }
 
     #[test]
     fn parses_option_command() {
         assert_eq!(
             ClientRequest::parse("OPTION formatted-passphrase"),
-            Some(ClientRequest::Option(PinentryOption::FormattedPassphrase))
+            Ok(ClientRequest::Option(PinentryOption::FormattedPassphrase))
         );
 
         assert_eq!(
             ClientRequest::parse("OPTION"),
-            Some(ClientRequest::Option(PinentryOption::UnknownOption))
+            Ok(ClientRequest::Option(PinentryOption::UnknownOption))
         );
     }
 
     #[test]
     fn parses_getpin_command() {
-        assert_eq!(ClientRequest::parse("GETPIN"), Some(ClientRequest::GetPin));
+        assert_eq!(ClientRequest::parse("GETPIN"), Ok(ClientRequest::GetPin));
     }
 
     #[test]
     fn parses_confirm_command() {
+        assert_eq!(ClientRequest::parse("CONFIRM"), Ok(ClientRequest::Confirm));
+    }
+
+    #[test]
+    fn parses_confirm_one_button_command() {
         assert_eq!(
-            ClientRequest::parse("CONFIRM"),
-            Some(ClientRequest::Confirm)
+            ClientRequest::parse("CONFIRM --one-button"),
+            Ok(ClientRequest::ConfirmOneButton)
         );
     }
 
     #[test]
     fn parses_message_command() {
-        assert_eq!(
-            ClientRequest::parse("MESSAGE"),
-            Some(ClientRequest::Message)
-        );
+        assert_eq!(ClientRequest::parse("MESSAGE"), Ok(ClientRequest::Message));
     }
 
     #[test]
     fn parses_settimeout_command() {
         assert_eq!(
             ClientRequest::parse("SETTIMEOUT 10"),
-            Some(ClientRequest::SetTimeout(10))
+            Ok(ClientRequest::SetTimeout(10))
         );
         assert_eq!(
             ClientRequest::parse("SETTIMEOUT"),
-            Some(ClientRequest::SetTimeout(0))
+            Ok(ClientRequest::SetTimeout(0))
         );
     }
 
@@ -197,7 +234,7 @@ mod tests {
     fn parses_setdesc_command() {
         assert_eq!(
             ClientRequest::parse("SETDESC Enter your password"),
-            Some(ClientRequest::SetDescription(
+            Ok(ClientRequest::SetDescription(
                 "Enter your password".to_string()
             ))
         );
@@ -207,7 +244,7 @@ mod tests {
     fn parses_setprompt_command() {
         assert_eq!(
             ClientRequest::parse("SETPROMPT Enter your password"),
-            Some(ClientRequest::SetPrompt("Enter your password".to_string()))
+            Ok(ClientRequest::SetPrompt("Enter your password".to_string()))
         );
     }
 
@@ -215,7 +252,7 @@ mod tests {
     fn parses_settitle_command() {
         assert_eq!(
             ClientRequest::parse("SETTITLE Enter your password"),
-            Some(ClientRequest::SetTitle("Enter your password".to_string()))
+            Ok(ClientRequest::SetTitle("Enter your password".to_string()))
         );
     }
 
@@ -223,7 +260,7 @@ mod tests {
     fn parses_setok_command() {
         assert_eq!(
             ClientRequest::parse("SETOK OK"),
-            Some(ClientRequest::SetOk("OK".to_string()))
+            Ok(ClientRequest::SetOk("OK".to_string()))
         );
     }
 
@@ -231,7 +268,7 @@ mod tests {
     fn parses_setcancel_command() {
         assert_eq!(
             ClientRequest::parse("SETCANCEL Cancel"),
-            Some(ClientRequest::SetCancel("Cancel".to_string()))
+            Ok(ClientRequest::SetCancel("Cancel".to_string()))
         );
     }
 
@@ -239,7 +276,7 @@ mod tests {
     fn parses_setnotok_command() {
         assert_eq!(
             ClientRequest::parse("SETNOTOK Not OK"),
-            Some(ClientRequest::SetNotOk("Not OK".to_string()))
+            Ok(ClientRequest::SetNotOk("Not OK".to_string()))
         );
     }
 
@@ -247,7 +284,7 @@ mod tests {
     fn parses_seterror_command() {
         assert_eq!(
             ClientRequest::parse("SETERROR Error"),
-            Some(ClientRequest::SetError("Error".to_string()))
+            Ok(ClientRequest::SetError("Error".to_string()))
         );
     }
 
@@ -255,7 +292,29 @@ mod tests {
     fn parses_setrepeat_command() {
         assert_eq!(
             ClientRequest::parse("SETREPEAT"),
-            Some(ClientRequest::SetRepeat)
+            Ok(ClientRequest::SetRepeat(None))
+        );
+        assert_eq!(
+            ClientRequest::parse("SETREPEAT Repeat:"),
+            Ok(ClientRequest::SetRepeat(Some("Repeat:".to_string())))
+        );
+    }
+
+    #[test]
+    fn parses_setrepeaterror_command() {
+        assert_eq!(
+            ClientRequest::parse("SETREPEATERROR does not match - try again"),
+            Ok(ClientRequest::SetRepeatError(
+                "does not match - try again".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_setrepeatok_command() {
+        assert_eq!(
+            ClientRequest::parse("SETREPEATOK Passphrases match."),
+            Ok(ClientRequest::SetRepeatOk("Passphrases match.".to_string()))
         );
     }
 
@@ -263,7 +322,7 @@ mod tests {
     fn parses_setqualitybar_command() {
         assert_eq!(
             ClientRequest::parse("SETQUALITYBAR"),
-            Some(ClientRequest::SetQualityBar)
+            Ok(ClientRequest::SetQualityBar)
         );
     }
 
@@ -271,7 +330,7 @@ mod tests {
     fn parses_setqualitybartooltip_command() {
         assert_eq!(
             ClientRequest::parse("SETQUALITYBARTOOLTIP Tooltip"),
-            Some(ClientRequest::SetQualityBarTooltip("Tooltip".to_string()))
+            Ok(ClientRequest::SetQualityBarTooltip("Tooltip".to_string()))
         );
     }
 
@@ -279,7 +338,7 @@ mod tests {
     fn parses_setgenpin_command() {
         assert_eq!(
             ClientRequest::parse("SETGENPIN"),
-            Some(ClientRequest::SetGenPin)
+            Ok(ClientRequest::SetGenPin)
         );
     }
 
@@ -287,7 +346,7 @@ mod tests {
     fn parses_setgenpintooltip_command() {
         assert_eq!(
             ClientRequest::parse("SETGENPINTOOLTIP Tooltip"),
-            Some(ClientRequest::SetGenPinTooltip("Tooltip".to_string()))
+            Ok(ClientRequest::SetGenPinTooltip("Tooltip".to_string()))
         );
     }
 
@@ -295,7 +354,97 @@ mod tests {
     fn parses_setkeyinfo_command() {
         assert_eq!(
             ClientRequest::parse("SETKEYINFO Key info"),
-            Some(ClientRequest::SetKeyInfo("Key info".to_string()))
+            Ok(ClientRequest::SetKeyInfo("Key info".to_string()))
+        );
+    }
+
+    #[test]
+    fn returns_unknown_ipc_command_error_for_anything_else() {
+        assert_eq!(
+            ClientRequest::parse("FOO"),
+            Err(AssuanError::UnknownIPCCommand)
+        );
+        assert_eq!(
+            ClientRequest::parse(""),
+            Err(AssuanError::UnknownIPCCommand)
+        );
+    }
+
+    #[test]
+    fn preserves_repeated_spaces_in_arguments() {
+        assert_eq!(
+            ClientRequest::parse("SETDESC Key:    ABCD  1234"),
+            Ok(ClientRequest::SetDescription(
+                "Key:    ABCD  1234".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn preserves_leading_and_trailing_spaces_in_arguments() {
+        assert_eq!(
+            ClientRequest::parse("SETPROMPT   PIN: "),
+            Ok(ClientRequest::SetPrompt("  PIN: ".to_string()))
+        );
+    }
+
+    #[test]
+    fn preserves_tabs_in_arguments() {
+        assert_eq!(
+            ClientRequest::parse("SETDESC Name:\tAlice\t"),
+            Ok(ClientRequest::SetDescription("Name:\tAlice\t".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_empty_arguments() {
+        assert_eq!(
+            ClientRequest::parse("SETDESC"),
+            Ok(ClientRequest::SetDescription("".to_string()))
+        );
+        assert_eq!(
+            ClientRequest::parse("SETDESC "),
+            Ok(ClientRequest::SetDescription("".to_string()))
+        );
+        assert_eq!(
+            ClientRequest::parse("SETOK  "),
+            Ok(ClientRequest::SetOk(" ".to_string()))
+        );
+    }
+
+    #[test]
+    fn decodes_escaped_arguments() {
+        assert_eq!(
+            ClientRequest::parse("SETDESC Enter your%0Apassword for 100%25"),
+            Ok(ClientRequest::SetDescription(
+                "Enter your\npassword for 100%".to_string()
+            ))
+        );
+        assert_eq!(
+            ClientRequest::parse("SETOK _Unlock%20key"),
+            Ok(ClientRequest::SetOk("_Unlock key".to_string()))
+        );
+        assert_eq!(
+            ClientRequest::parse("OPTION default-cancel=_Abort%21"),
+            Ok(ClientRequest::Option(PinentryOption::DefaultCancel(
+                "_Abort!".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn returns_invalid_value_error_for_malformed_escapes() {
+        assert_eq!(
+            ClientRequest::parse("SETDESC 100%"),
+            Err(AssuanError::InvalidValue)
+        );
+        assert_eq!(
+            ClientRequest::parse("SETPROMPT %G0"),
+            Err(AssuanError::InvalidValue)
+        );
+        assert_eq!(
+            ClientRequest::parse("OPTION default-ok=%4"),
+            Err(AssuanError::InvalidValue)
         );
     }
 }
@@ -0,0 +1,289 @@
+use std::{env, fs, io, path::PathBuf};
+
+use crate::{
+    backend::{auto::BackendKind, layer_shell::LayerShellOptions, scripted::ScriptedOptions},
+    generator::GeneratorOptions,
+    hardening::HardeningOptions,
+};
+
+/// Settings that do not come from the client, read from
+/// `$XDG_CONFIG_HOME/pinentry/pinentry.conf`. Each line is a `key = value`
+/// pair. `#` starts a comment at the start of a line or after whitespace,
+/// elsewhere it is part of the value, as in `file:/path#1`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// Only use this backend instead of picking one.
+    pub backend: Option<BackendKind>,
+    pub layer_shell: LayerShellOptions,
+    pub scripted: ScriptedOptions,
+    pub hardening: HardeningOptions,
+    pub generator: GeneratorOptions,
+}
+
+impl Config {
+    /// Reads the config file, if there is one. The override from
+    /// `PINENTRY_USER_DATA` is applied separately, so a bad one can be
+    /// ignored without losing the file's settings.
+    pub fn load() -> Result<Config, String> {
+        let config = match config_path() {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(contents) => Config::parse(&contents)
+                    .map_err(|error| format!("{}: {}", path.display(), error))?,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Config::default(),
+                Err(error) => return Err(format!("{}: {}", path.display(), error)),
+            },
+            None => Config::default(),
+        };
+        Ok(config)
+    }
+
+    /// Applies the override from `PINENTRY_USER_DATA`, if it is set.
+    pub fn apply_user_data_from_env(&mut self) -> Result<(), String> {
+        match env::var_os("PINENTRY_USER_DATA") {
+            Some(user_data) => self
+                .apply_user_data(&user_data.to_string_lossy())
+                .map_err(|error| format!("PINENTRY_USER_DATA: {}", error)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `key = value`", number + 1));
+            };
+            config
+                .set(key.trim(), value.trim())
+                .map_err(|error| format!("line {}: {}", number + 1, error))?;
+        }
+        Ok(config)
+    }
+
+    /// `PINENTRY_USER_DATA` is shared with other tools, so it is a comma
+    /// separated list of `key=value` pairs of which only `pinentry-backend`
+    /// is ours.
+    pub fn apply_user_data(&mut self, user_data: &str) -> Result<(), String> {
+        for pair in user_data.split(',') {
+            if let Some(("pinentry-backend", backend)) = pair.trim().split_once('=') {
+                self.set("backend", backend.trim())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "backend" => self.backend = Some(value.parse()?),
+            "anchor" => self.layer_shell.anchor = value.parse()?,
+            "margins" => self.layer_shell.margins = value.parse()?,
+            "output" => self.layer_shell.output = Some(value.to_string()),
+            "backdrop" => self.layer_shell.backdrop = parse_bool(value)?,
+            "scripted-pin" => self.scripted.pin = value.parse()?,
+            "scripted-confirm" => self.scripted.confirm = value.parse()?,
+            "mlockall" => self.hardening.mlockall = parse_bool(value)?,
+            "sandbox" => self.hardening.sandbox = parse_bool(value)?,
+            "generate-length" => self.generator.length = parse_count(value)?,
+            "generate-classes" => self.generator.classes = value.parse()?,
+            "generate-word-list" => self.generator.word_list = Some(value.into()),
+            "generate-words" => self.generator.words = parse_count(value)?,
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let comment = line.char_indices().find(|&(index, character)| {
+        character == '#'
+            && line[..index]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace)
+    });
+    match comment {
+        Some((index, _)) => &line[..index],
+        None => line,
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected true or false, got `{}`", value)),
+    }
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("expected a positive number, got `{}`", value)),
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("pinentry").join("pinentry.conf"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{
+        layer_shell::{Anchor, Margins},
+        scripted::{ConfirmPolicy, PinSource},
+    };
+
+    use super::*;
+
+    #[test]
+    fn parses_all_keys() {
+        let config = Config::parse(
+            "# pick the overlay\n\
+             backend = layer-shell\n\
+             anchor = top\n\
+             margins = 24 # from the top\n\
+             output = DP-1\n\
+             backdrop = yes\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                backend: Some(BackendKind::LayerShell),
+                layer_shell: LayerShellOptions {
+                    anchor: Anchor {
+                        top: true,
+                        ..Default::default()
+                    },
+                    margins: Margins {
+                        top: 24,
+                        right: 24,
+                        bottom: 24,
+                        left: 24,
+                    },
+                    output: Some("DP-1".to_string()),
+                    backdrop: true,
+                },
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parses_scripted_answers() {
+        let config = Config::parse(
+            "backend = scripted\n\
+             scripted-pin = fd:3\n\
+             scripted-confirm = always-yes\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.backend, Some(BackendKind::Scripted));
+        assert_eq!(
+            config.scripted,
+            ScriptedOptions {
+                pin: PinSource::Fd(3),
+                confirm: ConfirmPolicy::AlwaysYes,
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_hashes_inside_values() {
+        let config = Config::parse(
+            "\t# indented comment\n\
+             scripted-pin = file:/run/secrets/pin#1 # the first one\n\
+             output=#2\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.scripted.pin,
+            PinSource::File("/run/secrets/pin#1".into())
+        );
+        assert_eq!(config.layer_shell.output, Some("#2".to_string()));
+    }
+
+    #[test]
+    fn parses_hardening_switches() {
+        let config = Config::parse("mlockall = on\nsandbox = yes\n").unwrap();
+
+        assert_eq!(
+            config.hardening,
+            HardeningOptions {
+                mlockall: true,
+                sandbox: true,
+            }
+        );
+        assert_eq!(Config::default().hardening, HardeningOptions::default());
+    }
+
+    #[test]
+    fn parses_the_generator_policy() {
+        let config = Config::parse(
+            "generate-length = 32\n\
+             generate-classes = lower, digits\n\
+             generate-word-list = /usr/share/dict/eff_large_wordlist.txt\n\
+             generate-words = 7\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.generator,
+            GeneratorOptions {
+                length: 32,
+                classes: "lower, digits".parse().unwrap(),
+                word_list: Some("/usr/share/dict/eff_large_wordlist.txt".into()),
+                words: 7,
+            }
+        );
+        assert_eq!(
+            Config::parse("generate-words = 0\n"),
+            Err("line 1: expected a positive number, got `0`".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        assert_eq!(
+            Config::parse("\nbackend = qt\n"),
+            Err("line 2: unknown backend `qt`".to_string())
+        );
+        assert_eq!(
+            Config::parse("backdrop\n"),
+            Err("line 1: expected `key = value`".to_string())
+        );
+        assert_eq!(
+            Config::parse("colour = red\n"),
+            Err("line 1: unknown key `colour`".to_string())
+        );
+    }
+
+    #[test]
+    fn user_data_overrides_the_backend() {
+        let mut config = Config::parse("backend = gtk\nsandbox = on\n").unwrap();
+
+        config
+            .apply_user_data("profile=work, pinentry-backend=tty")
+            .unwrap();
+        assert_eq!(config.backend, Some(BackendKind::Tty));
+
+        assert_eq!(
+            config.apply_user_data("pinentry-backend=qt"),
+            Err("unknown backend `qt`".to_string())
+        );
+        // a bad override leaves the rest of the file in place
+        assert_eq!(config.backend, Some(BackendKind::Tty));
+        assert!(config.hardening.sandbox);
+    }
+}
@@ -1,9 +1,22 @@
 use std::fmt::Display;
 
+/// Error source of everything a pinentry reports, `GPG_ERR_SOURCE_PINENTRY`.
+const SOURCE_PINENTRY: u32 = 5;
+
 #[derive(Debug, PartialEq)]
 pub enum AssuanError {
     UnknownIPCCommand,
+    IPCParameterError,
     NotImplemented,
+    Canceled,
+    Timeout,
+    NotConfirmed,
+    InvalidValue,
+    LineTooLong,
+    SyntaxError,
+    UnknownOption,
+    NoPinEntry,
+    General,
 }
 
 impl Display for AssuanError {
@@ -22,31 +35,70 @@ impl AssuanError {
     pub fn description(&self) -> &str {
         match self {
             AssuanError::UnknownIPCCommand => "Unknown IPC command",
+            AssuanError::IPCParameterError => "IPC parameter error",
             AssuanError::NotImplemented => "Not implemented",
+            AssuanError::Canceled => "Operation cancelled",
+            AssuanError::Timeout => "Timeout",
+            AssuanError::NotConfirmed => "Not confirmed",
+            AssuanError::InvalidValue => "Invalid value",
+            AssuanError::LineTooLong => "Line too long",
+            AssuanError::SyntaxError => "Syntax error",
+            AssuanError::UnknownOption => "Unknown option",
+            AssuanError::NoPinEntry => "No pinentry",
+            AssuanError::General => "General error",
         }
     }
 
     pub fn code(&self) -> u32 {
         match self {
             AssuanError::UnknownIPCCommand => 275,
+            AssuanError::IPCParameterError => 280,
             AssuanError::NotImplemented => 69,
+            AssuanError::Canceled => 99,
+            AssuanError::Timeout => 62,
+            AssuanError::NotConfirmed => 114,
+            AssuanError::InvalidValue => 55,
+            AssuanError::LineTooLong => 263,
+            AssuanError::SyntaxError => 276,
+            AssuanError::UnknownOption => 174,
+            AssuanError::NoPinEntry => 85,
+            AssuanError::General => 1,
         }
     }
 
     pub fn source(&self) -> &str {
-        "<User defined source 1>"
+        "<Pinentry>"
     }
 
     pub fn assuan_code(&self) -> u32 {
-        // no idea why this should be 64 in binary,
-        // their docs says SOURCE_PINENTRY is 5, but every implementation uses this
-        let source_bits: u32 = 0b1000000; // 7 bits
-        let reserved_bits: u32 = 0b0000000; // seems to be 7 bits of zeros
-        let error_code: u32 = self.code(); // 16 bits from the `code` function
+        // gpg-error packs the source into the 7 bits above bit 24 and the
+        // error code into the lowest 16 bits, the bits in between are reserved
+        (SOURCE_PINENTRY << 24) | self.code()
+    }
 
-        let assuan_code = (source_bits << 23) | (reserved_bits << 16) | error_code;
+    /// Maps a gpg-error value back to the error, ignoring its source bits.
+    pub fn from_code(code: u32) -> Option<AssuanError> {
+        match code & 0xffff {
+            275 => Some(AssuanError::UnknownIPCCommand),
+            280 => Some(AssuanError::IPCParameterError),
+            69 => Some(AssuanError::NotImplemented),
+            99 => Some(AssuanError::Canceled),
+            62 => Some(AssuanError::Timeout),
+            114 => Some(AssuanError::NotConfirmed),
+            55 => Some(AssuanError::InvalidValue),
+            263 => Some(AssuanError::LineTooLong),
+            276 => Some(AssuanError::SyntaxError),
+            174 => Some(AssuanError::UnknownOption),
+            85 => Some(AssuanError::NoPinEntry),
+            1 => Some(AssuanError::General),
+            _ => None,
+        }
+    }
 
-        assuan_code
+    /// Decodes an `ERR <code> <description>` line as sent by a pinentry.
+    pub fn parse(line: &str) -> Option<AssuanError> {
+        let code = line.strip_prefix("ERR ")?.split(' ').next()?;
+        AssuanError::from_code(code.parse().ok()?)
     }
 }
 
@@ -57,33 +109,184 @@ mod tests {
     #[test]
     fn unknown_ipc_error_has_correct_code_and_description() {
         assert_eq!(AssuanError::UnknownIPCCommand.code(), 275);
-        assert_eq!(AssuanError::UnknownIPCCommand.assuan_code(), 536871187);
+        assert_eq!(AssuanError::UnknownIPCCommand.assuan_code(), 83886355);
         assert_eq!(
             AssuanError::UnknownIPCCommand.description(),
             "Unknown IPC command"
         );
+        assert_eq!(AssuanError::UnknownIPCCommand.source(), "<Pinentry>");
         assert_eq!(
-            AssuanError::UnknownIPCCommand.source(),
-            "<User defined source 1>"
+            AssuanError::UnknownIPCCommand.to_string(),
+            "ERR 83886355 Unknown IPC command <Pinentry>"
         );
+    }
+
+    #[test]
+    fn ipc_parameter_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::IPCParameterError.code(), 280);
+        assert_eq!(AssuanError::IPCParameterError.assuan_code(), 83886360);
         assert_eq!(
-            AssuanError::UnknownIPCCommand.to_string(),
-            "ERR 536871187 Unknown IPC command <User defined source 1>"
+            AssuanError::IPCParameterError.description(),
+            "IPC parameter error"
+        );
+        assert_eq!(
+            AssuanError::IPCParameterError.to_string(),
+            "ERR 83886360 IPC parameter error <Pinentry>"
         );
     }
 
     #[test]
     fn not_implemented_error_has_correct_code_and_description() {
         assert_eq!(AssuanError::NotImplemented.code(), 69);
-        assert_eq!(AssuanError::NotImplemented.assuan_code(), 536870981);
+        assert_eq!(AssuanError::NotImplemented.assuan_code(), 83886149);
         assert_eq!(AssuanError::NotImplemented.description(), "Not implemented");
+        assert_eq!(AssuanError::NotImplemented.source(), "<Pinentry>");
         assert_eq!(
-            AssuanError::NotImplemented.source(),
-            "<User defined source 1>"
+            AssuanError::NotImplemented.to_string(),
+            "ERR 83886149 Not implemented <Pinentry>"
         );
+    }
+
+    #[test]
+    fn canceled_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::Canceled.code(), 99);
+        assert_eq!(AssuanError::Canceled.assuan_code(), 83886179);
+        assert_eq!(AssuanError::Canceled.description(), "Operation cancelled");
         assert_eq!(
-            AssuanError::NotImplemented.to_string(),
-            "ERR 536870981 Not implemented <User defined source 1>"
+            AssuanError::Canceled.to_string(),
+            "ERR 83886179 Operation cancelled <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn timeout_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::Timeout.code(), 62);
+        assert_eq!(AssuanError::Timeout.assuan_code(), 83886142);
+        assert_eq!(AssuanError::Timeout.description(), "Timeout");
+        assert_eq!(
+            AssuanError::Timeout.to_string(),
+            "ERR 83886142 Timeout <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn not_confirmed_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::NotConfirmed.code(), 114);
+        assert_eq!(AssuanError::NotConfirmed.assuan_code(), 83886194);
+        assert_eq!(AssuanError::NotConfirmed.description(), "Not confirmed");
+        assert_eq!(
+            AssuanError::NotConfirmed.to_string(),
+            "ERR 83886194 Not confirmed <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn invalid_value_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::InvalidValue.code(), 55);
+        assert_eq!(AssuanError::InvalidValue.assuan_code(), 83886135);
+        assert_eq!(AssuanError::InvalidValue.description(), "Invalid value");
+        assert_eq!(
+            AssuanError::InvalidValue.to_string(),
+            "ERR 83886135 Invalid value <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn line_too_long_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::LineTooLong.code(), 263);
+        assert_eq!(AssuanError::LineTooLong.assuan_code(), 83886343);
+        assert_eq!(AssuanError::LineTooLong.description(), "Line too long");
+        assert_eq!(
+            AssuanError::LineTooLong.to_string(),
+            "ERR 83886343 Line too long <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn syntax_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::SyntaxError.code(), 276);
+        assert_eq!(AssuanError::SyntaxError.assuan_code(), 83886356);
+        assert_eq!(AssuanError::SyntaxError.description(), "Syntax error");
+        assert_eq!(
+            AssuanError::SyntaxError.to_string(),
+            "ERR 83886356 Syntax error <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn unknown_option_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::UnknownOption.code(), 174);
+        assert_eq!(AssuanError::UnknownOption.assuan_code(), 83886254);
+        assert_eq!(AssuanError::UnknownOption.description(), "Unknown option");
+        assert_eq!(
+            AssuanError::UnknownOption.to_string(),
+            "ERR 83886254 Unknown option <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn no_pin_entry_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::NoPinEntry.code(), 85);
+        assert_eq!(AssuanError::NoPinEntry.assuan_code(), 83886165);
+        assert_eq!(AssuanError::NoPinEntry.description(), "No pinentry");
+        assert_eq!(
+            AssuanError::NoPinEntry.to_string(),
+            "ERR 83886165 No pinentry <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn general_error_has_correct_code_and_description() {
+        assert_eq!(AssuanError::General.code(), 1);
+        assert_eq!(AssuanError::General.assuan_code(), 83886081);
+        assert_eq!(AssuanError::General.description(), "General error");
+        assert_eq!(
+            AssuanError::General.to_string(),
+            "ERR 83886081 General error <Pinentry>"
+        );
+    }
+
+    #[test]
+    fn from_code_round_trips_every_error() {
+        for error in [
+            AssuanError::UnknownIPCCommand,
+            AssuanError::IPCParameterError,
+            AssuanError::NotImplemented,
+            AssuanError::Canceled,
+            AssuanError::Timeout,
+            AssuanError::NotConfirmed,
+            AssuanError::InvalidValue,
+            AssuanError::LineTooLong,
+            AssuanError::SyntaxError,
+            AssuanError::UnknownOption,
+            AssuanError::NoPinEntry,
+            AssuanError::General,
+        ] {
+            assert_eq!(AssuanError::from_code(error.assuan_code()), Some(error));
+        }
+    }
+
+    #[test]
+    fn from_code_ignores_the_source() {
+        assert_eq!(
+            AssuanError::from_code(536871011),
+            Some(AssuanError::Canceled)
+        );
+        assert_eq!(AssuanError::from_code(99), Some(AssuanError::Canceled));
+        assert_eq!(AssuanError::from_code(83886080), None);
+    }
+
+    #[test]
+    fn parses_err_lines() {
+        assert_eq!(
+            AssuanError::parse("ERR 83886179 Operation cancelled <Pinentry>"),
+            Some(AssuanError::Canceled)
+        );
+        assert_eq!(
+            AssuanError::parse("ERR 83886142"),
+            Some(AssuanError::Timeout)
         );
+        assert_eq!(AssuanError::parse("OK"), None);
+        assert_eq!(AssuanError::parse("ERR foo"), None);
     }
 }
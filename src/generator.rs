@@ -0,0 +1,279 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::secret::{self, Secret};
+
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/// The kinds of characters a generated passphrase is made of. Each of them
+/// shows up at least once if the passphrase is long enough.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CharacterClasses {
+    pub lower: bool,
+    pub upper: bool,
+    pub digits: bool,
+    pub symbols: bool,
+}
+
+impl Default for CharacterClasses {
+    fn default() -> Self {
+        CharacterClasses {
+            lower: true,
+            upper: true,
+            digits: true,
+            symbols: true,
+        }
+    }
+}
+
+impl CharacterClasses {
+    fn sets(&self) -> Vec<&'static str> {
+        [
+            (self.lower, LOWER),
+            (self.upper, UPPER),
+            (self.digits, DIGITS),
+            (self.symbols, SYMBOLS),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, set)| enabled.then_some(set))
+        .collect()
+    }
+}
+
+impl FromStr for CharacterClasses {
+    type Err = String;
+
+    /// Takes a comma separated list such as `lower, upper, digits`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut classes = CharacterClasses {
+            lower: false,
+            upper: false,
+            digits: false,
+            symbols: false,
+        };
+        for class in input.split(',').map(str::trim) {
+            match class {
+                "lower" => classes.lower = true,
+                "upper" => classes.upper = true,
+                "digits" => classes.digits = true,
+                "symbols" => classes.symbols = true,
+                _ => return Err(format!("unknown character class `{}`", class)),
+            }
+        }
+        Ok(classes)
+    }
+}
+
+/// How `SETGENPIN` passphrases are made.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorOptions {
+    /// Number of characters, unless a word list is used.
+    pub length: usize,
+    pub classes: CharacterClasses,
+    /// Makes passphrases of words from this file instead, one per line.
+    /// Diceware lists with the dice rolls in front of each word work as they
+    /// are.
+    pub word_list: Option<PathBuf>,
+    /// Number of words taken from the word list.
+    pub words: usize,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            length: 24,
+            classes: CharacterClasses::default(),
+            word_list: None,
+            words: 6,
+        }
+    }
+}
+
+impl GeneratorOptions {
+    /// Draws a new passphrase from the kernel's random number generator.
+    pub fn generate(&self) -> io::Result<Secret> {
+        match &self.word_list {
+            Some(path) => generate_words(&read_word_list(path)?, self.words),
+            None => generate_characters(&self.classes.sets(), self.length),
+        }
+    }
+}
+
+fn read_word_list(path: &Path) -> io::Result<Vec<String>> {
+    let words: Vec<String> = fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .map(str::to_string)
+        .collect();
+
+    if words.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has too few words", path.display()),
+        ));
+    }
+    Ok(words)
+}
+
+fn generate_words(words: &[String], count: usize) -> io::Result<Secret> {
+    let mut passphrase = Secret::new();
+    for position in 0..count {
+        if position > 0 {
+            passphrase.push(' ');
+        }
+        passphrase.push_str(&words[random_below(words.len())?]);
+    }
+    Ok(passphrase)
+}
+
+fn generate_characters(sets: &[&str], length: usize) -> io::Result<Secret> {
+    let alphabet: Vec<char> = sets.concat().chars().collect();
+    if alphabet.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no character classes to generate from",
+        ));
+    }
+
+    // starting over until every class shows up keeps all passphrases that
+    // qualify equally likely
+    loop {
+        let mut passphrase = Secret::new();
+        for _ in 0..length {
+            passphrase.push(alphabet[random_below(alphabet.len())?]);
+        }
+
+        let has_every_class = sets
+            .iter()
+            .all(|set| passphrase.expose().chars().any(|c| set.contains(c)));
+        if has_every_class || length < sets.len() {
+            return Ok(passphrase);
+        }
+    }
+}
+
+/// A uniformly distributed number below `bound`.
+fn random_below(bound: usize) -> io::Result<usize> {
+    let bound = bound as u64;
+    // the lowest values would come up once more often than the rest
+    let skipped = bound.wrapping_neg() % bound;
+    loop {
+        let mut bytes = [0; 8];
+        fill_random(&mut bytes)?;
+        let value = u64::from_ne_bytes(bytes);
+        secret::wipe(&mut bytes);
+
+        if value >= skipped {
+            return Ok((value % bound) as usize);
+        }
+    }
+}
+
+fn fill_random(buffer: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let remaining = &mut buffer[filled..];
+        let result = unsafe { libc::getrandom(remaining.as_mut_ptr().cast(), remaining.len(), 0) };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+            continue;
+        }
+        filled += result as usize;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn parses_character_classes() {
+        assert_eq!(
+            "lower, digits".parse(),
+            Ok(CharacterClasses {
+                lower: true,
+                upper: false,
+                digits: true,
+                symbols: false,
+            })
+        );
+        assert_eq!(
+            "lower, emoji".parse::<CharacterClasses>(),
+            Err("unknown character class `emoji`".to_string())
+        );
+    }
+
+    #[test]
+    fn generates_from_every_class() {
+        let options = GeneratorOptions {
+            length: 8,
+            classes: "upper, digits".parse().unwrap(),
+            ..Default::default()
+        };
+
+        for _ in 0..20 {
+            let passphrase = options.generate().unwrap();
+            let passphrase = passphrase.expose();
+
+            assert_eq!(passphrase.len(), 8);
+            assert!(passphrase
+                .chars()
+                .all(|c| UPPER.contains(c) || DIGITS.contains(c)));
+            assert!(passphrase.chars().any(|c| UPPER.contains(c)));
+            assert!(passphrase.chars().any(|c| DIGITS.contains(c)));
+        }
+    }
+
+    #[test]
+    fn generates_from_a_diceware_list() {
+        let path = env::temp_dir().join(format!("pinentry-words-{}", std::process::id()));
+        fs::write(&path, "11111\tabacus\n11112\tabdomen\n\n11113\tabide\n").unwrap();
+
+        let options = GeneratorOptions {
+            word_list: Some(path.clone()),
+            words: 4,
+            ..Default::default()
+        };
+        let passphrase = options.generate();
+        fs::remove_file(path).unwrap();
+
+        let passphrase = passphrase.unwrap();
+        let words: Vec<&str> = passphrase.expose().split(' ').collect();
+        assert_eq!(words.len(), 4);
+        assert!(words
+            .iter()
+            .all(|word| ["abacus", "abdomen", "abide"].contains(word)));
+    }
+
+    #[test]
+    fn rejects_word_lists_without_choice() {
+        let path = env::temp_dir().join(format!("pinentry-one-word-{}", std::process::id()));
+        fs::write(&path, "11111 abacus\n").unwrap();
+
+        let result = read_word_list(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn random_numbers_cover_the_whole_range() {
+        let mut seen = [false; 6];
+        for _ in 0..600 {
+            seen[random_below(6).unwrap()] = true;
+        }
+        assert_eq!(seen, [true; 6]);
+    }
+}
@@ -0,0 +1,608 @@
+use std::{
+    fs::OpenOptions,
+    io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::fs::OpenOptionsExt,
+    },
+    path::PathBuf,
+    ptr,
+};
+
+/// Hardening that is off by default because it can get in the way.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HardeningOptions {
+    /// Locks all of the process into memory, not just the passphrases. Needs
+    /// an `RLIMIT_MEMLOCK` that fits GTK.
+    pub mlockall: bool,
+    /// Applies [`sandbox`] once the backend for the first dialog is up.
+    pub sandbox: bool,
+}
+
+/// Keeps the passphrases out of core dumps and away from debuggers run by
+/// the same user. Called first thing at startup.
+pub fn harden_process() -> io::Result<()> {
+    let no_core = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    check(unsafe { libc::setrlimit(libc::RLIMIT_CORE, &no_core) })?;
+    // also keeps the user from reading /proc/<pid>/mem
+    check(unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) })
+}
+
+pub fn lock_all_memory() -> io::Result<()> {
+    check(unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) })
+}
+
+/// The syscalls a backend keeps once the sandbox is in place.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Syscalls {
+    /// Memory, threads, signals, and I/O on the Assuan stream, terminals and
+    /// files.
+    #[default]
+    Basic,
+    /// Also what GTK needs to talk to the display server and D-Bus, and to
+    /// share buffers with the compositor.
+    Graphical,
+}
+
+/// What the sandbox leaves to the backend that was picked.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SandboxProfile {
+    /// Files and directories that can be read.
+    pub readable: Vec<PathBuf>,
+    /// Files and directories that can also be written to and changed.
+    pub writable: Vec<PathBuf>,
+    pub syscalls: Syscalls,
+}
+
+/// Restricts the process to the files and syscalls in `profile`. Other files
+/// cannot be opened, programs cannot be run, and other processes cannot be
+/// traced or reached into. Paths that do not exist are skipped.
+///
+/// Call it once the backend is initialized, after GTK has opened its display
+/// and started its threads. The syscall filter applies to every thread, but
+/// Landlock only restricts the calling thread and the ones it starts later.
+/// Kernels without Landlock only get the syscall filter.
+pub fn sandbox(profile: &SandboxProfile) -> io::Result<()> {
+    check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+    restrict_files(&profile.readable, &profile.writable)?;
+    restrict_syscalls(profile.syscalls)
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    match result {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_uint = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+/// Everything from removing directories to making symlinks.
+const ACCESS_FS_MODIFY_DIR: u64 = 0b1_1111_1111 << 4;
+/// Since version 3 of the Landlock ABI.
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+/// The rights that can be given for a single file rather than a directory.
+const ACCESS_FS_FILE: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: libc::c_int,
+}
+
+fn restrict_files(readable: &[PathBuf], writable: &[PathBuf]) -> io::Result<()> {
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            ptr::null::<RulesetAttr>(),
+            0,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 1 {
+        return Ok(());
+    }
+
+    let mut handled = ACCESS_FS_EXECUTE
+        | ACCESS_FS_WRITE_FILE
+        | ACCESS_FS_READ_FILE
+        | ACCESS_FS_READ_DIR
+        | ACCESS_FS_MODIFY_DIR;
+    if abi >= 3 {
+        handled |= ACCESS_FS_TRUNCATE;
+    }
+
+    let attr = RulesetAttr {
+        handled_access_fs: handled,
+    };
+    let ruleset = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr,
+            mem::size_of_val(&attr),
+            0,
+        )
+    };
+    if ruleset < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: a new descriptor that nothing else owns
+    let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset as libc::c_int) };
+
+    let rules = readable
+        .iter()
+        .map(|path| (path, ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR))
+        .chain(
+            writable
+                .iter()
+                .map(|path| (path, handled & !ACCESS_FS_EXECUTE)),
+        );
+    for (path, access) in rules {
+        // a path that does not exist cannot be used either
+        let Ok(file) = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH)
+            .open(path)
+        else {
+            continue;
+        };
+        let allowed_access = match file.metadata() {
+            Ok(metadata) if metadata.is_dir() => access,
+            _ => access & ACCESS_FS_FILE,
+        };
+
+        let rule = PathBeneathAttr {
+            allowed_access,
+            parent_fd: file.as_raw_fd(),
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                LANDLOCK_RULE_PATH_BENEATH,
+                &rule,
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    match unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Every backend makes these, and so do the standard library and glib.
+/// `clone`, `ioctl`, `prctl` and `socket` are only allowed with some
+/// arguments, see [`restrict_syscalls`].
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const BASIC_SYSCALLS: &[libc::c_long] = &[
+    // files, terminals and the Assuan stream
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_lseek,
+    libc::SYS_openat,
+    libc::SYS_close,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_readlinkat,
+    libc::SYS_getdents64,
+    libc::SYS_getcwd,
+    libc::SYS_fgetxattr,
+    libc::SYS_getxattr,
+    libc::SYS_lgetxattr,
+    libc::SYS_fcntl,
+    libc::SYS_flock,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_ftruncate,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_fchmod,
+    libc::SYS_utimensat,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat2,
+    libc::SYS_umask,
+    // waiting
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_eventfd2,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_settime,
+    libc::SYS_timerfd_gettime,
+    libc::SYS_nanosleep,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_getres,
+    libc::SYS_gettimeofday,
+    // memory
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_msync,
+    libc::SYS_mlock,
+    libc::SYS_membarrier,
+    // threads and signals
+    libc::SYS_futex,
+    libc::SYS_set_robust_list,
+    libc::SYS_set_tid_address,
+    libc::SYS_rseq,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_sched_getattr,
+    libc::SYS_sched_setattr,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_tgkill,
+    libc::SYS_restart_syscall,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    // about the process
+    libc::SYS_getpid,
+    libc::SYS_getppid,
+    libc::SYS_gettid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_getresuid,
+    libc::SYS_getresgid,
+    libc::SYS_getgroups,
+    libc::SYS_prlimit64,
+    libc::SYS_uname,
+    libc::SYS_sysinfo,
+    libc::SYS_getrandom,
+];
+
+/// The older variants glibc still uses on x86_64.
+#[cfg(target_arch = "x86_64")]
+const LEGACY_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_open,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_access,
+    libc::SYS_readlink,
+    libc::SYS_getdents,
+    libc::SYS_dup2,
+    libc::SYS_pipe,
+    libc::SYS_mkdir,
+    libc::SYS_unlink,
+    libc::SYS_rename,
+    libc::SYS_renameat,
+    libc::SYS_poll,
+    libc::SYS_select,
+    libc::SYS_epoll_wait,
+    libc::SYS_eventfd,
+    libc::SYS_inotify_init,
+    libc::SYS_getrlimit,
+    libc::SYS_time,
+    libc::SYS_arch_prctl,
+];
+#[cfg(target_arch = "aarch64")]
+const LEGACY_SYSCALLS: &[libc::c_long] = &[];
+
+/// Added for GTK: sockets to the display server and D-Bus, memory shared with
+/// the compositor, and watching settings and themes for changes.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const GRAPHICAL_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_shutdown,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_memfd_create,
+    libc::SYS_fallocate,
+    libc::SYS_inotify_init1,
+    libc::SYS_inotify_add_watch,
+    libc::SYS_inotify_rm_watch,
+];
+
+/// The `prctl` options glibc and GTK use: naming threads and mappings.
+/// Anything else, `PR_SET_DUMPABLE` in particular, could undo
+/// [`harden_process`].
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const PRCTL_OPTIONS: &[libc::c_int] = &[
+    libc::PR_SET_NAME,
+    libc::PR_GET_NAME,
+    libc::PR_SET_VMA,
+    libc::PR_GET_DUMPABLE,
+];
+
+/// `clone` flags that would put a thread into new namespaces.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const NAMESPACE_FLAGS: libc::c_int = libc::CLONE_NEWNS
+    | libc::CLONE_NEWCGROUP
+    | libc::CLONE_NEWUTS
+    | libc::CLONE_NEWIPC
+    | libc::CLONE_NEWUSER
+    | libc::CLONE_NEWPID
+    | libc::CLONE_NEWNET;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Fails every syscall that is not allowed for `syscalls` with `EPERM`.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn restrict_syscalls(syscalls: Syscalls) -> io::Result<()> {
+    use libc::{BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_JSET, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+    let statement = |code: u32, k| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |comparison: u32, k, jt, jf| libc::sock_filter {
+        code: (BPF_JMP | comparison | BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    };
+    let load = |offset: usize| statement(BPF_LD | BPF_W | BPF_ABS, offset as u32);
+    // the lower half of an argument, which is all the checks below look at
+    let load_arg = |index: usize| {
+        load(mem::offset_of!(libc::seccomp_data, args) + index * mem::size_of::<u64>())
+    };
+    let allow = statement(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW);
+    let fail =
+        |errno: libc::c_int| statement(BPF_RET | BPF_K, libc::SECCOMP_RET_ERRNO | errno as u32);
+    let deny = fail(libc::EPERM);
+
+    // syscall numbers of other architectures mean something else
+    let mut filter = vec![
+        load(mem::offset_of!(libc::seccomp_data, arch)),
+        jump(BPF_JEQ, AUDIT_ARCH, 1, 0),
+        deny,
+        load(mem::offset_of!(libc::seccomp_data, nr)),
+    ];
+    if cfg!(target_arch = "x86_64") {
+        // x32 syscalls share the architecture, but not the numbers
+        filter.extend([jump(BPF_JGE, 0x4000_0000, 0, 1), deny]);
+    }
+
+    let mut allowed = [BASIC_SYSCALLS, LEGACY_SYSCALLS].concat();
+    if syscalls == Syscalls::Graphical {
+        allowed.extend(GRAPHICAL_SYSCALLS);
+    }
+    // each comparison skips the `allow` that follows it unless it matched
+    for syscall in allowed {
+        filter.extend([jump(BPF_JEQ, syscall as u32, 0, 1), allow]);
+    }
+
+    // threads, but no new namespaces
+    filter.extend([
+        jump(BPF_JEQ, libc::SYS_clone as u32, 0, 4),
+        load_arg(0),
+        jump(BPF_JSET, NAMESPACE_FLAGS as u32, 1, 0),
+        allow,
+        deny,
+    ]);
+    // naming threads, but not undoing the hardening
+    let options = PRCTL_OPTIONS.len();
+    filter.extend([
+        jump(BPF_JEQ, libc::SYS_prctl as u32, 0, options as u8 + 3),
+        load_arg(0),
+    ]);
+    for (index, option) in PRCTL_OPTIONS.iter().enumerate() {
+        filter.push(jump(BPF_JEQ, *option as u32, (options - index) as u8, 0));
+    }
+    filter.extend([deny, allow]);
+    // the flags of clone3 cannot be checked, so glibc is made to fall back
+    // to clone
+    filter.extend([
+        jump(BPF_JEQ, libc::SYS_clone3 as u32, 0, 1),
+        fail(libc::ENOSYS),
+    ]);
+    // no input pushed into the client's terminal
+    filter.extend([
+        jump(BPF_JEQ, libc::SYS_ioctl as u32, 0, 5),
+        load_arg(1),
+        jump(BPF_JEQ, libc::TIOCSTI as u32, 2, 0),
+        jump(BPF_JEQ, libc::TIOCLINUX as u32, 1, 0),
+        allow,
+        deny,
+    ]);
+    if syscalls == Syscalls::Graphical {
+        // no network, only local sockets
+        filter.extend([
+            jump(BPF_JEQ, libc::SYS_socket as u32, 0, 4),
+            load_arg(0),
+            jump(BPF_JEQ, libc::AF_UNIX as u32, 0, 1),
+            allow,
+            deny,
+        ]);
+    }
+    filter.push(deny);
+
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    match unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &program,
+        )
+    } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn restrict_syscalls(_syscalls: Syscalls) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, process::Command};
+
+    use super::*;
+
+    /// Set to the id of the test process in the child that applies a test's
+    /// restrictions.
+    const CHILD: &str = "PINENTRY_HARDENING_CHILD";
+
+    /// Whether this is the child process that should apply the restrictions of
+    /// `test`. If not, runs `test` again in a child process, so its
+    /// restrictions stay out of the test harness, and checks that it passed.
+    fn in_child(test: &str) -> bool {
+        if env::var_os(CHILD).is_some() {
+            return true;
+        }
+
+        // the harness leaves out the crate name
+        let (_, module) = module_path!().split_once("::").unwrap();
+        let status = Command::new(env::current_exe().unwrap())
+            .args([
+                &format!("{}::{}", module, test),
+                "--exact",
+                "--test-threads=1",
+            ])
+            .env(CHILD, process::id().to_string())
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} failed in the child: {}", test, status);
+        false
+    }
+
+    fn proc_self(file: &str, field: &str) -> String {
+        fs::read_to_string(format!("/proc/self/{}", file))
+            .unwrap()
+            .lines()
+            .find_map(|line| line.strip_prefix(field))
+            .unwrap()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn landlock_abi() -> libc::c_long {
+        unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                ptr::null::<RulesetAttr>(),
+                0,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        }
+    }
+
+    #[test]
+    fn no_core_dumps_and_not_dumpable() {
+        if !in_child("no_core_dumps_and_not_dumpable") {
+            return;
+        }
+        harden_process().unwrap();
+
+        assert_eq!(proc_self("limits", "Max core file size"), "0 0 bytes");
+        assert_eq!(unsafe { libc::prctl(libc::PR_GET_DUMPABLE) }, 0);
+    }
+
+    #[test]
+    fn sandbox_only_allows_the_profile() {
+        let test_process = env::var(CHILD).unwrap_or_else(|_| process::id().to_string());
+        let root = env::temp_dir().join(format!("pinentry-sandbox-{}", test_process));
+        if !in_child("sandbox_only_allows_the_profile") {
+            fs::remove_dir_all(root).unwrap();
+            return;
+        }
+        let (readable, writable, elsewhere) = (
+            root.join("readable"),
+            root.join("writable"),
+            root.join("elsewhere"),
+        );
+        for directory in [&readable, &writable, &elsewhere] {
+            fs::create_dir_all(directory).unwrap();
+            fs::write(directory.join("file"), "secret").unwrap();
+        }
+
+        // the filter does not allow asking later
+        let landlock = landlock_abi() >= 1;
+        sandbox(&SandboxProfile {
+            // for the checks below
+            readable: vec![readable.clone(), PathBuf::from("/proc")],
+            writable: vec![writable.clone()],
+            syscalls: Syscalls::Basic,
+        })
+        .unwrap();
+
+        assert_eq!(proc_self("status", "NoNewPrivs:"), "1");
+        assert_eq!(proc_self("status", "Seccomp:"), "2");
+        assert!(Command::new("/bin/true").status().is_err());
+        assert_eq!(unsafe { libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0) }, -1);
+        assert_eq!(unsafe { libc::unshare(libc::CLONE_NEWUSER) }, -1);
+        assert_eq!(unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0) }, -1);
+        assert_eq!(unsafe { libc::prctl(libc::PR_GET_DUMPABLE) }, 1);
+        std::thread::Builder::new()
+            .name("named".to_string())
+            .spawn(|| ())
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(
+            unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0) },
+            -1
+        );
+        assert_eq!(
+            unsafe { libc::ioctl(libc::STDIN_FILENO, libc::TIOCSTI, b"x".as_ptr()) },
+            -1
+        );
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+
+        assert_eq!(fs::read_to_string(readable.join("file")).unwrap(), "secret");
+        fs::write(writable.join("file"), "allowed").unwrap();
+        if landlock {
+            assert!(fs::write(readable.join("file"), "denied").is_err());
+            assert!(fs::read(elsewhere.join("file")).is_err());
+            assert!(fs::write(elsewhere.join("file"), "denied").is_err());
+        }
+    }
+}
@@ -1,35 +1,33 @@
 use std::{
-    io::{BufRead, Write},
-    vec,
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, BufRead, Write},
 };
 
-use client_request::ClientRequest;
-use error::AssuanError;
-use response::Response;
+use backend::{gtk::GtkBackend, BackendError, DialogOptions, PinOutcome, PinentryBackend};
+use session::{DialogOutcome, DialogRequest, Output, Session};
 
+pub mod backend;
 pub mod client_request;
+pub mod config;
 pub mod error;
+pub mod generator;
+pub mod hardening;
+pub mod main_context;
 pub mod option;
+pub mod percent;
 pub mod response;
+pub mod secret;
+pub mod session;
+pub mod strength;
 
-pub struct Pinentry<R, W> {
+/// Serves a [`Session`] over a blocking reader and writer, showing each dialog
+/// through `backend` until it is closed.
+pub struct Pinentry<R, W, B = GtkBackend> {
     reader: R,
     writer: W,
-    timeout: Option<i32>,
-    description: Option<String>,
-    prompt: Option<String>,
-    title: Option<String>,
-    ok_button: Option<String>,
-    cancel_button: Option<String>,
-    not_ok_button: Option<String>,
-    error: Option<String>,
-    repeat: bool,
-    quality_bar: bool,
-    quality_bar_tooltip: Option<String>,
-    generate_pin: bool,
-    generate_pin_tooltip: Option<String>,
-    key_info: Option<String>,
-    should_quit: bool,
+    backend: B,
+    session: Session,
 }
 
 impl<R, W> Pinentry<R, W>
@@ -38,130 +36,187 @@ where
     W: Write,
 {
     pub fn new(reader: R, writer: W) -> Self {
+        Pinentry::with_backend(reader, writer, GtkBackend::default())
+    }
+}
+
+impl<R, W, B> Pinentry<R, W, B>
+where
+    R: BufRead,
+    W: Write,
+    B: PinentryBackend,
+{
+    pub fn with_backend(reader: R, writer: W, backend: B) -> Self {
         Pinentry {
             reader,
             writer,
-            timeout: None,
-            description: None,
-            prompt: None,
-            title: None,
-            ok_button: None,
-            cancel_button: None,
-            not_ok_button: None,
-            error: None,
-            repeat: false,
-            quality_bar: false,
-            quality_bar_tooltip: None,
-            generate_pin: false,
-            generate_pin_tooltip: None,
-            key_info: None,
-            should_quit: false,
+            backend,
+            session: Session::new(),
         }
     }
 
-    pub fn run(&mut self) {
-        let greeting = Response::Ok(Some("Pleased to meet you".to_string())).to_string();
-        writeln!(self.writer, "{}", greeting).unwrap();
+    /// Serves requests until the client says `BYE` or closes the connection.
+    pub fn run(&mut self) -> io::Result<()> {
+        self.greet()?;
+        while !self.session.is_closed() {
+            self.serve_line()?;
+        }
+        Ok(())
+    }
 
-        loop {
-            let mut command = String::new();
-            self.reader.read_line(&mut command).unwrap();
-            command.pop();
+    fn greet(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "{}", self.session.greeting())?;
+        self.writer.flush()
+    }
 
-            let responses = self.handle_request(ClientRequest::parse(&command));
-            for response in responses {
-                writeln!(self.writer, "{}", response).unwrap();
+    /// Reads the next line from the client and answers it, showing a dialog
+    /// if it asks for one. Blocks until the line is complete.
+    fn serve_line(&mut self) -> io::Result<()> {
+        let mut line = Vec::new();
+        let outputs = if self.reader.read_until(b'\n', &mut line)? == 0 {
+            // the client went away, nobody is left to answer
+            self.session.eof()
+        } else {
+            if !line.ends_with(b"\n") {
+                line.push(b'\n');
             }
+            // the options sent so far decide which backend is asked
+            let flavor = self.backend.flavor(&self.session.dialog_options());
+            self.session.set_flavor(flavor);
+            self.session.feed(&line)
+        };
+
+        if let Err(error) = self.handle_outputs(outputs) {
+            self.session.eof();
+            return Err(error);
+        }
+        Ok(())
+    }
 
-            if self.should_quit {
-                break;
+    fn handle_outputs(&mut self, outputs: Vec<Output>) -> io::Result<()> {
+        let mut outputs = VecDeque::from(outputs);
+        while let Some(output) = outputs.pop_front() {
+            match output {
+                Output::Response(response) => writeln!(self.writer, "{}", response)?,
+                Output::Dialog(request) => {
+                    self.writer.flush()?;
+                    match self.show_dialog(request) {
+                        Ok(outcome) => outputs.extend(self.session.finish_dialog(outcome)),
+                        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                            // the client went away while a dialog was waiting for it
+                            outputs.extend(self.session.eof());
+                        }
+                        Err(error) => return Err(error),
+                    }
+                }
+                Output::Quality(_) | Output::PinChecked(_) | Output::Closed => {}
             }
         }
+        self.writer.flush()
     }
 
-    fn handle_request(&mut self, request: Option<ClientRequest>) -> Vec<Response> {
-        match request {
-            Some(request) => match request {
-                ClientRequest::Bye => {
-                    self.should_quit = true;
-                    vec![Response::Ok(Some("Closing connection".to_string()))]
-                }
-                ClientRequest::SetTimeout(timeout) => {
-                    self.timeout = Some(timeout);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetDescription(desc) => {
-                    self.description = Some(desc);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetPrompt(prompt) => {
-                    self.prompt = Some(prompt);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetTitle(title) => {
-                    self.title = Some(title);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetOk(ok_button) => {
-                    self.ok_button = Some(ok_button);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetCancel(cancel_button) => {
-                    self.cancel_button = Some(cancel_button);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetNotOk(not_ok_button) => {
-                    self.not_ok_button = Some(not_ok_button);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetError(error) => {
-                    self.error = Some(error);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetRepeat => {
-                    self.repeat = true;
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetQualityBar => {
-                    self.quality_bar = true;
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetQualityBarTooltip(tooltip) => {
-                    self.quality_bar_tooltip = Some(tooltip);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetGenPin => {
-                    self.generate_pin = true;
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetGenPinTooltip(tooltip) => {
-                    self.generate_pin_tooltip = Some(tooltip);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::SetKeyInfo(key_info) => {
-                    self.key_info = Some(key_info);
-                    vec![Response::Ok(None)]
-                }
-                ClientRequest::Reset => {
-                    self.timeout = None;
-                    self.description = None;
-                    self.prompt = None;
-                    self.title = None;
-                    self.ok_button = None;
-                    self.cancel_button = None;
-                    self.not_ok_button = None;
-                    self.error = None;
-                    self.repeat = false;
-                    self.quality_bar = false;
-                    self.quality_bar_tooltip = None;
-                    self.generate_pin = false;
-                    self.generate_pin_tooltip = None;
-                    self.key_info = None;
-                    vec![Response::Ok(None)]
-                }
-                _ => vec![Response::Error(AssuanError::NotImplemented)],
+    fn show_dialog(&mut self, request: Box<DialogRequest>) -> io::Result<DialogOutcome> {
+        let outcome = match *request {
+            DialogRequest::GetPin(options) => self.get_pin(options).map(DialogOutcome::Pin),
+            DialogRequest::Confirm(options) => {
+                self.backend.confirm(&options).map(DialogOutcome::Confirm)
+            }
+            DialogRequest::Message(options) => {
+                self.backend.message(&options).map(DialogOutcome::Message)
+            }
+        };
+
+        match outcome {
+            Ok(outcome) => Ok(outcome),
+            Err(BackendError::Io(error)) => Err(error),
+            Err(BackendError::Unavailable(_)) => Ok(DialogOutcome::Failed),
+        }
+    }
+
+    /// Lets the dialog inquire the client about passphrases while it is open.
+    fn get_pin(&mut self, options: DialogOptions) -> Result<PinOutcome, BackendError> {
+        // both inquiries talk to the client, one at a time
+        let client = RefCell::new((&mut self.session, &mut self.reader, &mut self.writer));
+        self.backend.get_pin(
+            &options,
+            &mut |passphrase| {
+                let (session, reader, writer) = &mut *client.borrow_mut();
+                inquire_quality(session, reader, writer, passphrase)
             },
-            None => vec![Response::Error(AssuanError::UnknownIPCCommand)],
+            &mut |passphrase| {
+                let (session, reader, writer) = &mut *client.borrow_mut();
+                inquire_checkpin(session, reader, writer, passphrase)
+            },
+        )
+    }
+}
+
+/// Asks the client to rate `passphrase` and blocks until it has answered.
+fn inquire_quality<R: BufRead, W: Write>(
+    session: &mut Session,
+    reader: &mut R,
+    writer: &mut W,
+    passphrase: &str,
+) -> io::Result<Option<i32>> {
+    let outputs = session.inquire_quality(passphrase);
+    let score = inquire(session, reader, writer, outputs, |output| match output {
+        Output::Quality(score) => Some(score),
+        _ => None,
+    })?;
+    Ok(score.flatten())
+}
+
+/// Asks the client whether `passphrase` may be used and blocks until it has
+/// answered. Returns why it may not.
+fn inquire_checkpin<R: BufRead, W: Write>(
+    session: &mut Session,
+    reader: &mut R,
+    writer: &mut W,
+    passphrase: &str,
+) -> io::Result<Option<String>> {
+    let outputs = session.inquire_checkpin(passphrase);
+    let error = inquire(session, reader, writer, outputs, |output| match output {
+        Output::PinChecked(error) => Some(error),
+        _ => None,
+    })?;
+    Ok(error.flatten())
+}
+
+/// Sends the inquiry in `outputs` and feeds the client's lines to `session`
+/// until `answer` picks the answer out of its outputs. Returns nothing if no
+/// inquiry could be made.
+fn inquire<R: BufRead, W: Write, T>(
+    session: &mut Session,
+    reader: &mut R,
+    writer: &mut W,
+    outputs: Vec<Output>,
+    answer: impl Fn(Output) -> Option<T>,
+) -> io::Result<Option<T>> {
+    if outputs.is_empty() {
+        return Ok(None);
+    }
+    for output in outputs {
+        if let Output::Response(response) = output {
+            writeln!(writer, "{}", response)?;
+        }
+    }
+    writer.flush()?;
+
+    loop {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        for output in session.feed(&line) {
+            match output {
+                Output::Response(response) => writeln!(writer, "{}", response)?,
+                output => {
+                    if let Some(answer) = answer(output) {
+                        return Ok(Some(answer));
+                    }
+                }
+            }
         }
     }
 }
@@ -171,6 +226,82 @@ mod tests {
     use std::io::Cursor;
 
     use super::*;
+    use backend::{
+        scripted::{ScriptedBackend, ScriptedOptions},
+        ConfirmOutcome, MessageOutcome,
+    };
+
+    /// Answers each dialog with the next scripted outcome and remembers what
+    /// it was shown with.
+    #[derive(Default)]
+    struct TestBackend {
+        pins: VecDeque<PinOutcome>,
+        confirmations: VecDeque<ConfirmOutcome>,
+        unavailable: bool,
+        shown: Vec<DialogOptions>,
+        scores: Vec<Option<i32>>,
+        /// Why the client rejected pins under `constraints-enforce`.
+        rejections: Vec<String>,
+    }
+
+    impl PinentryBackend for TestBackend {
+        fn get_pin(
+            &mut self,
+            options: &DialogOptions,
+            quality: &mut dyn FnMut(&str) -> io::Result<Option<i32>>,
+            check_pin: &mut dyn FnMut(&str) -> io::Result<Option<String>>,
+        ) -> Result<PinOutcome, BackendError> {
+            if self.unavailable {
+                return Err(BackendError::Unavailable("scripted".to_string()));
+            }
+            self.shown.push(options.clone());
+
+            // the next pin is entered into the same dialog after a rejection
+            loop {
+                let outcome = self.pins.pop_front().unwrap_or(PinOutcome::Canceled);
+                let PinOutcome::Pin(pin) = &outcome else {
+                    return Ok(outcome);
+                };
+                if options.quality_bar {
+                    self.scores
+                        .push(quality(pin.expose()).map_err(BackendError::Io)?);
+                }
+                if !options.constraints_enforce {
+                    return Ok(outcome);
+                }
+                match check_pin(pin.expose()).map_err(BackendError::Io)? {
+                    Some(error) => self.rejections.push(error),
+                    None => return Ok(outcome),
+                }
+            }
+        }
+
+        fn confirm(&mut self, options: &DialogOptions) -> Result<ConfirmOutcome, BackendError> {
+            self.shown.push(options.clone());
+            Ok(self
+                .confirmations
+                .pop_front()
+                .unwrap_or(ConfirmOutcome::Canceled))
+        }
+
+        fn message(&mut self, options: &DialogOptions) -> Result<MessageOutcome, BackendError> {
+            self.shown.push(options.clone());
+            Ok(MessageOutcome::Dismissed)
+        }
+
+        fn flavor(&self, _options: &DialogOptions) -> &'static str {
+            "test"
+        }
+    }
+
+    fn run_with_backend(input: Vec<&str>, backend: TestBackend) -> (String, TestBackend) {
+        let input_buffer = Cursor::new((input.join("\n") + "\n").into_bytes());
+        let mut pinentry = Pinentry::with_backend(input_buffer, Vec::new(), backend);
+        pinentry.run().unwrap();
+
+        let output = String::from_utf8(pinentry.writer).unwrap();
+        (output, pinentry.backend)
+    }
 
     fn assert_input_produces_output(
         input: Vec<&str>,
@@ -180,7 +311,7 @@ mod tests {
         let output_buffer = Cursor::new(Vec::new());
 
         let mut pinentry = Pinentry::new(input_buffer, output_buffer);
-        pinentry.run();
+        pinentry.run().unwrap();
 
         assert_eq!(
             output.join("\n") + "\n",
@@ -196,7 +327,7 @@ mod tests {
             vec!["BYE"],
             vec!["OK Pleased to meet you", "OK Closing connection"],
         );
-        assert_eq!(pinentry.should_quit, true);
+        assert!(pinentry.session.is_closed());
     }
 
     #[test]
@@ -205,7 +336,10 @@ mod tests {
             vec!["SETDESC Hello, world!", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.description, Some("Hello, world!".to_string()));
+        assert_eq!(
+            pinentry.session.description,
+            Some("Hello, world!".to_string())
+        );
     }
 
     #[test]
@@ -214,7 +348,7 @@ mod tests {
             vec!["SETTIMEOUT 10", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.timeout, Some(10));
+        assert_eq!(pinentry.session.timeout, Some(10));
     }
 
     #[test]
@@ -223,7 +357,10 @@ mod tests {
             vec!["SETPROMPT Enter your password", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.prompt, Some("Enter your password".to_string()));
+        assert_eq!(
+            pinentry.session.prompt,
+            Some("Enter your password".to_string())
+        );
     }
 
     #[test]
@@ -232,7 +369,10 @@ mod tests {
             vec!["SETTITLE Enter your password", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.title, Some("Enter your password".to_string()));
+        assert_eq!(
+            pinentry.session.title,
+            Some("Enter your password".to_string())
+        );
     }
 
     #[test]
@@ -241,7 +381,7 @@ mod tests {
             vec!["SETOK OK", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.ok_button, Some("OK".to_string()));
+        assert_eq!(pinentry.session.ok_button, Some("OK".to_string()));
     }
 
     #[test]
@@ -250,7 +390,7 @@ mod tests {
             vec!["SETCANCEL Cancel", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.cancel_button, Some("Cancel".to_string()));
+        assert_eq!(pinentry.session.cancel_button, Some("Cancel".to_string()));
     }
 
     #[test]
@@ -259,7 +399,7 @@ mod tests {
             vec!["SETNOTOK Not OK", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.not_ok_button, Some("Not OK".to_string()));
+        assert_eq!(pinentry.session.not_ok_button, Some("Not OK".to_string()));
     }
 
     #[test]
@@ -268,7 +408,7 @@ mod tests {
             vec!["SETERROR Error message", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.error, Some("Error message".to_string()));
+        assert_eq!(pinentry.session.error, Some("Error message".to_string()));
     }
 
     #[test]
@@ -277,7 +417,7 @@ mod tests {
             vec!["SETREPEAT", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.repeat, true);
+        assert_eq!(pinentry.session.repeat, true);
     }
 
     #[test]
@@ -286,7 +426,7 @@ mod tests {
             vec!["SETQUALITYBAR", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.quality_bar, true);
+        assert_eq!(pinentry.session.quality_bar, true);
     }
 
     #[test]
@@ -295,7 +435,10 @@ mod tests {
             vec!["SETQUALITYBARTOOLTIP Tooltip", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.quality_bar_tooltip, Some("Tooltip".to_string()));
+        assert_eq!(
+            pinentry.session.quality_bar_tooltip,
+            Some("Tooltip".to_string())
+        );
     }
 
     #[test]
@@ -304,7 +447,7 @@ mod tests {
             vec!["SETGENPIN", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.generate_pin, true);
+        assert_eq!(pinentry.session.generate_pin, true);
     }
 
     #[test]
@@ -313,7 +456,10 @@ mod tests {
             vec!["SETGENPINTOOLTIP Tooltip", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.generate_pin_tooltip, Some("Tooltip".to_string()));
+        assert_eq!(
+            pinentry.session.generate_pin_tooltip,
+            Some("Tooltip".to_string())
+        );
     }
 
     #[test]
@@ -322,7 +468,209 @@ mod tests {
             vec!["SETKEYINFO Key info", "BYE"],
             vec!["OK Pleased to meet you", "OK", "OK Closing connection"],
         );
-        assert_eq!(pinentry.key_info, Some("Key info".to_string()));
+        assert_eq!(pinentry.session.key_info, Some("Key info".to_string()));
+    }
+
+    #[test]
+    fn default_option_commands_set_button_labels() {
+        let pinentry = assert_input_produces_output(
+            vec![
+                "OPTION default-ok=_OK",
+                "OPTION default-cancel=_Cancel",
+                "OPTION default-prompt=PIN:",
+                "SETOK Unlock",
+                "BYE",
+            ],
+            vec![
+                "OK Pleased to meet you",
+                "OK",
+                "OK",
+                "OK",
+                "OK",
+                "OK Closing connection",
+            ],
+        );
+
+        let options = pinentry.session.dialog_options();
+        assert_eq!(options.ok_button.as_deref(), Some("Unlock"));
+        assert_eq!(options.cancel_button.as_deref(), Some("_Cancel"));
+        assert_eq!(options.prompt.as_deref(), Some("PIN:"));
+    }
+
+    #[test]
+    fn unknown_option_returns_unknown_option_error() {
+        assert_input_produces_output(
+            vec!["OPTION foo=bar", "BYE"],
+            vec![
+                "OK Pleased to meet you",
+                "ERR 83886254 Unknown option <Pinentry>",
+                "OK Closing connection",
+            ],
+        );
+    }
+
+    #[test]
+    fn getinfo_flavor_comes_from_the_backend() {
+        let (output, _) = run_with_backend(vec!["GETINFO flavor", "BYE"], TestBackend::default());
+        assert_eq!(
+            output,
+            "OK Pleased to meet you\nD test\nOK\nOK Closing connection\n"
+        );
+    }
+
+    #[test]
+    fn getinfo_flavor_returns_flavor() {
+        assert_input_produces_output(
+            vec!["GETINFO flavor", "BYE"],
+            vec![
+                "OK Pleased to meet you",
+                "D gtk4",
+                "OK",
+                "OK Closing connection",
+            ],
+        );
+    }
+
+    #[test]
+    fn getinfo_version_returns_package_version() {
+        let version = format!("D {}", env!("CARGO_PKG_VERSION"));
+        assert_input_produces_output(
+            vec!["GETINFO version", "BYE"],
+            vec![
+                "OK Pleased to meet you",
+                &version,
+                "OK",
+                "OK Closing connection",
+            ],
+        );
+    }
+
+    #[test]
+    fn getinfo_pid_returns_process_id() {
+        let pid = format!("D {}", std::process::id());
+        assert_input_produces_output(
+            vec!["GETINFO pid", "BYE"],
+            vec![
+                "OK Pleased to meet you",
+                &pid,
+                "OK",
+                "OK Closing connection",
+            ],
+        );
+    }
+
+    #[test]
+    fn getinfo_ttyinfo_returns_tty_options() {
+        assert_input_produces_output(
+            vec![
+                "GETINFO ttyinfo",
+                "OPTION ttyname=/dev/pts/3",
+                "OPTION ttytype=xterm-256color",
+                "OPTION display=:0",
+                "GETINFO ttyinfo",
+                "BYE",
+            ],
+            vec![
+                "OK Pleased to meet you",
+                "D - - -",
+                "OK",
+                "OK",
+                "OK",
+                "OK",
+                "D /dev/pts/3 xterm-256color :0",
+                "OK",
+                "OK Closing connection",
+            ],
+        );
+    }
+
+    #[test]
+    fn getinfo_unknown_subcommand_returns_parameter_error() {
+        assert_input_produces_output(
+            vec!["GETINFO foo", "BYE"],
+            vec![
+                "OK Pleased to meet you",
+                "ERR 83886360 IPC parameter error <Pinentry>",
+                "OK Closing connection",
+            ],
+        );
+    }
+
+    #[test]
+    fn inquire_quality_returns_client_score() {
+        let mut session = Session::new();
+        session.feed(b"GETPIN\n");
+        let mut reader = Cursor::new(b"D 4\nD 2\nEND\n".to_vec());
+        let mut writer = Cursor::new(Vec::new());
+
+        let score = inquire_quality(&mut session, &mut reader, &mut writer, "100%");
+
+        assert_eq!(score.unwrap(), Some(42));
+        assert_eq!(writer.get_ref(), b"INQUIRE QUALITY 100%25\n");
+    }
+
+    #[test]
+    fn inquire_quality_returns_none_when_client_cancels() {
+        let mut session = Session::new();
+        session.feed(b"GETPIN\n");
+        let mut reader = Cursor::new(b"D 42\nCAN\n".to_vec());
+        let mut writer = Cursor::new(Vec::new());
+
+        let score = inquire_quality(&mut session, &mut reader, &mut writer, "secret");
+        assert_eq!(score.unwrap(), None);
+    }
+
+    #[test]
+    fn inquire_quality_fails_when_client_disconnects() {
+        let mut session = Session::new();
+        session.feed(b"GETPIN\n");
+        let mut reader = Cursor::new(b"D 42\n".to_vec());
+        let mut writer = Cursor::new(Vec::new());
+
+        let error = inquire_quality(&mut session, &mut reader, &mut writer, "secret").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn end_of_input_stops_and_wipes_state() {
+        let input_buffer = Cursor::new(b"SETDESC Hello\nSETKEYINFO n/1234\n".to_vec());
+        let mut pinentry = Pinentry::new(input_buffer, Cursor::new(Vec::new()));
+
+        assert!(pinentry.run().is_ok());
+        assert_eq!(pinentry.session.description, None);
+        assert_eq!(pinentry.session.key_info, None);
+        assert_eq!(
+            String::from_utf8(pinentry.writer.get_ref().to_vec()).unwrap(),
+            "OK Pleased to meet you\nOK\nOK\n"
+        );
+    }
+
+    #[test]
+    fn last_line_without_newline_is_handled() {
+        let input_buffer = Cursor::new(b"SETDESC Hello\nBYE".to_vec());
+        let mut pinentry = Pinentry::new(input_buffer, Cursor::new(Vec::new()));
+
+        pinentry.run().unwrap();
+        assert!(pinentry.session.is_closed());
+    }
+
+    #[test]
+    fn write_errors_are_returned() {
+        struct BrokenPipe;
+
+        impl Write for BrokenPipe {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut pinentry = Pinentry::new(Cursor::new(b"BYE\n".to_vec()), BrokenPipe);
+        let error = pinentry.run().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
     }
 
     #[test]
@@ -336,7 +684,9 @@ mod tests {
                 "SETCANCEL Cancel",
                 "SETNOTOK Not OK",
                 "SETERROR Error message",
-                "SETREPEAT",
+                "SETREPEAT Repeat:",
+                "SETREPEATERROR Mismatch",
+                "SETREPEATOK Match",
                 "SETQUALITYBAR",
                 "SETQUALITYBARTOOLTIP Tooltip",
                 "SETGENPIN",
@@ -361,23 +711,28 @@ mod tests {
                 "OK",
                 "OK",
                 "OK",
+                "OK",
+                "OK",
                 "OK Closing connection",
             ],
         );
 
-        assert_eq!(pinentry.description, None);
-        assert_eq!(pinentry.prompt, None);
-        assert_eq!(pinentry.title, None);
-        assert_eq!(pinentry.ok_button, None);
-        assert_eq!(pinentry.cancel_button, None);
-        assert_eq!(pinentry.not_ok_button, None);
-        assert_eq!(pinentry.error, None);
-        assert_eq!(pinentry.repeat, false);
-        assert_eq!(pinentry.quality_bar, false);
-        assert_eq!(pinentry.quality_bar_tooltip, None);
-        assert_eq!(pinentry.generate_pin, false);
-        assert_eq!(pinentry.generate_pin_tooltip, None);
-        assert_eq!(pinentry.key_info, None);
+        assert_eq!(pinentry.session.description, None);
+        assert_eq!(pinentry.session.prompt, None);
+        assert_eq!(pinentry.session.title, None);
+        assert_eq!(pinentry.session.ok_button, None);
+        assert_eq!(pinentry.session.cancel_button, None);
+        assert_eq!(pinentry.session.not_ok_button, None);
+        assert_eq!(pinentry.session.error, None);
+        assert_eq!(pinentry.session.repeat, false);
+        assert_eq!(pinentry.session.repeat_prompt, None);
+        assert_eq!(pinentry.session.repeat_error, None);
+        assert_eq!(pinentry.session.repeat_ok, None);
+        assert_eq!(pinentry.session.quality_bar, false);
+        assert_eq!(pinentry.session.quality_bar_tooltip, None);
+        assert_eq!(pinentry.session.generate_pin, false);
+        assert_eq!(pinentry.session.generate_pin_tooltip, None);
+        assert_eq!(pinentry.session.key_info, None);
     }
 
     #[test]
@@ -386,10 +741,37 @@ mod tests {
             vec!["CANCEL", "BYE"],
             vec![
                 "OK Pleased to meet you",
-                "ERR 536870981 Not implemented <User defined source 1>",
+                "ERR 83886149 Not implemented <Pinentry>",
+                "OK Closing connection",
+            ],
+        );
+    }
+
+    #[test]
+    fn malformed_escape_returns_invalid_value_error() {
+        let pinentry = assert_input_produces_output(
+            vec!["SETDESC 100%", "BYE"],
+            vec![
+                "OK Pleased to meet you",
+                "ERR 83886135 Invalid value <Pinentry>",
+                "OK Closing connection",
+            ],
+        );
+        assert_eq!(pinentry.session.description, None);
+    }
+
+    #[test]
+    fn overlong_line_returns_line_too_long_error() {
+        let description = format!("SETDESC {}", "a".repeat(1000));
+        let pinentry = assert_input_produces_output(
+            vec![&description, "BYE"],
+            vec![
+                "OK Pleased to meet you",
+                "ERR 83886343 Line too long <Pinentry>",
                 "OK Closing connection",
             ],
         );
+        assert_eq!(pinentry.session.description, None);
     }
 
     #[test]
@@ -398,9 +780,121 @@ mod tests {
             vec!["FOO", "BYE"],
             vec![
                 "OK Pleased to meet you",
-                "ERR 536871187 Unknown IPC command <User defined source 1>",
+                "ERR 83886355 Unknown IPC command <Pinentry>",
                 "OK Closing connection",
             ],
         );
     }
+
+    #[test]
+    fn getpin_shows_the_backend_dialog() {
+        let backend = TestBackend {
+            pins: VecDeque::from([PinOutcome::Pin("secret".into())]),
+            ..Default::default()
+        };
+        let (output, backend) =
+            run_with_backend(vec!["SETDESC Unlock key", "GETPIN", "BYE"], backend);
+
+        assert_eq!(
+            output,
+            "OK Pleased to meet you\nOK\nD secret\nOK\nOK Closing connection\n"
+        );
+        assert_eq!(backend.shown.len(), 1);
+        assert_eq!(backend.shown[0].description.as_deref(), Some("Unlock key"));
+    }
+
+    #[test]
+    fn getpin_quality_callback_inquires_the_client() {
+        let backend = TestBackend {
+            pins: VecDeque::from([PinOutcome::Pin("secret".into())]),
+            ..Default::default()
+        };
+        let (output, backend) = run_with_backend(
+            vec!["SETQUALITYBAR", "GETPIN", "D 42", "END", "BYE"],
+            backend,
+        );
+
+        assert_eq!(
+            output,
+            "OK Pleased to meet you\nOK\nINQUIRE QUALITY secret\nD secret\nOK\nOK Closing connection\n"
+        );
+        assert_eq!(backend.scores, vec![Some(42)]);
+    }
+
+    #[test]
+    fn getpin_asks_again_while_the_client_rejects_the_pin() {
+        let backend = TestBackend {
+            pins: VecDeque::from([
+                PinOutcome::Pin("short".into()),
+                PinOutcome::Pin("long enough".into()),
+            ]),
+            ..Default::default()
+        };
+        let (output, backend) = run_with_backend(
+            vec![
+                "OPTION constraints-enforce",
+                "OPTION constraints-error-title=Passphrase Not Allowed",
+                "GETPIN",
+                "D Too short",
+                "END",
+                "END",
+                "BYE",
+            ],
+            backend,
+        );
+
+        assert_eq!(
+            output,
+            "OK Pleased to meet you\nOK\nOK\nINQUIRE CHECKPIN short\nINQUIRE CHECKPIN long enough\nD long enough\nOK\nOK Closing connection\n"
+        );
+        // all in the same dialog
+        assert_eq!(backend.shown.len(), 1);
+        assert_eq!(backend.rejections, vec!["Too short"]);
+    }
+
+    #[test]
+    fn confirm_and_message_use_the_backend() {
+        let backend = TestBackend {
+            confirmations: VecDeque::from([ConfirmOutcome::NotConfirmed]),
+            ..Default::default()
+        };
+        let (output, backend) = run_with_backend(vec!["CONFIRM", "MESSAGE", "BYE"], backend);
+
+        assert_eq!(
+            output,
+            "OK Pleased to meet you\nERR 83886194 Not confirmed <Pinentry>\nOK\nOK Closing connection\n"
+        );
+        assert_eq!(backend.shown.len(), 2);
+    }
+
+    #[test]
+    fn scripted_backend_serves_the_protocol() {
+        let backend = ScriptedBackend::new(ScriptedOptions {
+            confirm: "n/1234=yes".parse().unwrap(),
+            ..Default::default()
+        });
+        let input =
+            Cursor::new(b"SETKEYINFO n/1234\nCONFIRM\nSETERROR Bad\nGETPIN\nBYE\n".to_vec());
+        let mut pinentry = Pinentry::with_backend(input, Vec::new(), backend);
+        pinentry.run().unwrap();
+
+        assert_eq!(
+            String::from_utf8(pinentry.writer).unwrap(),
+            "OK Pleased to meet you\nOK\nOK\nOK\nERR 83886179 Operation cancelled <Pinentry>\nOK Closing connection\n"
+        );
+    }
+
+    #[test]
+    fn unavailable_backend_returns_general_error() {
+        let backend = TestBackend {
+            unavailable: true,
+            ..Default::default()
+        };
+        let (output, _) = run_with_backend(vec!["GETPIN", "BYE"], backend);
+
+        assert_eq!(
+            output,
+            "OK Pleased to meet you\nERR 83886081 General error <Pinentry>\nOK Closing connection\n"
+        );
+    }
 }
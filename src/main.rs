@@ -1,9 +1,52 @@
-use std::io::{stdin, stdout};
+use std::{
+    io::{stdin, stdout},
+    os::fd::AsRawFd,
+    process::ExitCode,
+};
 
-use pinentry::Pinentry;
+use pinentry::{
+    backend::{
+        auto::{AutoBackend, Displays},
+        PinentryBackend,
+    },
+    config::Config,
+    hardening, Pinentry,
+};
 
-fn main() {
-    let mut pinentry = Pinentry::new(stdin().lock(), stdout());
+fn main() -> ExitCode {
+    if let Err(error) = hardening::harden_process() {
+        eprintln!("pinentry: hardening: {}", error);
+    }
 
-    pinentry.run();
-}
\ No newline at end of file
+    // the defaults would run without the sandbox the file may turn on
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("pinentry: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    // only picks the backend, the file's hardening settings stay
+    if let Err(error) = config.apply_user_data_from_env() {
+        eprintln!("pinentry: {}", error);
+    }
+    if config.hardening.mlockall {
+        if let Err(error) = hardening::lock_all_memory() {
+            eprintln!("pinentry: mlockall: {}", error);
+        }
+    }
+
+    // applies the sandbox, if it is turned on, before the first dialog
+    let mut backend = AutoBackend::new(config, Displays::from_env());
+    // dialogs are closed when gpg-agent goes away
+    backend.watch_client(stdin().as_raw_fd());
+    let mut pinentry = Pinentry::with_backend(stdin().lock(), stdout(), backend);
+
+    match pinentry.run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("pinentry: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
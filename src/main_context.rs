@@ -0,0 +1,148 @@
+use std::{
+    cell::RefCell,
+    io::{self, BufReader, Read, Write},
+    os::fd::AsRawFd,
+    rc::Rc,
+};
+
+use gtk4::glib;
+
+use crate::{backend::PinentryBackend, Pinentry};
+
+/// Serves `pinentry` from the default GLib main context until the client says
+/// `BYE` or closes the connection, so the UI and the protocol share one
+/// thread. Requests are only read once the client's descriptor is readable,
+/// in between the context goes on with whatever else is attached to it.
+///
+/// Dialogs are shown while the descriptor's source is dispatched. GLib does
+/// not dispatch a source again before it has returned, so a backend can keep
+/// iterating the context while its dialog is open. A line that arrives in
+/// pieces blocks until it is complete.
+pub fn run<T, W, B>(mut pinentry: Pinentry<BufReader<T>, W, B>) -> io::Result<()>
+where
+    T: Read + AsRawFd + 'static,
+    W: Write + 'static,
+    B: PinentryBackend + 'static,
+{
+    pinentry.greet()?;
+
+    let fd = pinentry.reader.get_ref().as_raw_fd();
+    let main_loop = glib::MainLoop::new(None, false);
+    let result = Rc::new(RefCell::new(Ok(())));
+    let condition = glib::IOCondition::IN | glib::IOCondition::HUP | glib::IOCondition::ERR;
+    glib::unix_fd_add_local(fd, condition, {
+        let main_loop = main_loop.clone();
+        let result = result.clone();
+        move |_, _| {
+            // lines read along with an earlier one wait in the buffer, the
+            // descriptor does not become readable for them
+            let served = loop {
+                if let Err(error) = pinentry.serve_line() {
+                    break Err(error);
+                }
+                if pinentry.session.is_closed() || !pinentry.reader.buffer().contains(&b'\n') {
+                    break Ok(());
+                }
+            };
+
+            if served.is_ok() && !pinentry.session.is_closed() {
+                return glib::ControlFlow::Continue;
+            }
+            *result.borrow_mut() = served;
+            main_loop.quit();
+            glib::ControlFlow::Break
+        }
+    });
+
+    main_loop.run();
+    result.replace(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        os::unix::net::UnixStream,
+        sync::{Mutex, PoisonError},
+    };
+
+    use super::*;
+    use crate::backend::scripted::{PinSource, ScriptedBackend, ScriptedOptions};
+
+    /// Only one thread at a time can own the default main context.
+    static DEFAULT_CONTEXT: Mutex<()> = Mutex::new(());
+
+    struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_with_input<B>(input: &[u8], backend: B) -> String
+    where
+        B: PinentryBackend + 'static,
+    {
+        let _owner = DEFAULT_CONTEXT
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(input).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let writer = SharedWriter(output.clone());
+        run(Pinentry::with_backend(
+            BufReader::new(server),
+            writer,
+            backend,
+        ))
+        .unwrap();
+
+        let output = output.borrow().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn scripted() -> ScriptedBackend {
+        ScriptedBackend::new(ScriptedOptions::default())
+    }
+
+    #[test]
+    fn serves_requests_until_bye() {
+        assert_eq!(
+            run_with_input(b"SETDESC Hello\nBYE\nNOP\n", scripted()),
+            "OK Pleased to meet you\nOK\nOK Closing connection\n"
+        );
+    }
+
+    #[test]
+    fn stops_at_end_of_input() {
+        assert_eq!(
+            run_with_input(b"GETINFO flavor\n", scripted()),
+            "OK Pleased to meet you\nD scripted\nOK\n"
+        );
+    }
+
+    #[test]
+    fn shows_dialogs_through_the_backend() {
+        let pin = env::temp_dir().join(format!("pinentry-main-context-{}", std::process::id()));
+        fs::write(&pin, "secret\n").unwrap();
+        let backend = ScriptedBackend::new(ScriptedOptions {
+            pin: PinSource::File(pin.clone()),
+            ..Default::default()
+        });
+
+        let output = run_with_input(b"GETPIN\nCONFIRM\nBYE\n", backend);
+        fs::remove_file(pin).unwrap();
+
+        assert_eq!(
+            output,
+            "OK Pleased to meet you\nD secret\nOK\nERR 83886194 Not confirmed <Pinentry>\nOK Closing connection\n"
+        );
+    }
+}
@@ -1,35 +1,51 @@
+use crate::{error::AssuanError, percent};
+
 #[derive(Debug, PartialEq)]
 pub enum PinentryOption {
     FormattedPassphrase,
     FormattedPassphraseHint(String),
     TtyType(String),
     TtYName(String),
+    Display(String),
     LccType(String),
     DefaultOk(String),
     DefaultCancel(String),
     DefaultPrompt(String),
     AllowExternalPasswordCache,
+    ConstraintsEnforce,
+    ConstraintsHintShort(String),
+    ConstraintsHintLong(String),
+    ConstraintsErrorTitle(String),
     UnknownOption,
 }
 
 impl PinentryOption {
-    pub fn parse(input: &str) -> PinentryOption {
-        let parts: Vec<&str> = input.split("=").collect();
-        let get_second_part = |default: &str| parts.get(1).unwrap_or(&default).to_string();
-        match parts[0] {
+    pub fn parse(input: &str) -> Result<PinentryOption, AssuanError> {
+        let (name, value) = input.split_once('=').unwrap_or((input, ""));
+        let option = match name {
             "formatted-passphrase" => PinentryOption::FormattedPassphrase,
             "formatted-passphrase-hint" => {
-                PinentryOption::FormattedPassphraseHint(get_second_part(""))
+                PinentryOption::FormattedPassphraseHint(percent::decode(value)?)
             }
-            "ttytype" => PinentryOption::TtyType(get_second_part("")),
-            "ttyname" => PinentryOption::TtYName(get_second_part("")),
-            "lc-ctype" => PinentryOption::LccType(get_second_part("")),
-            "default-ok" => PinentryOption::DefaultOk(get_second_part("")),
-            "default-cancel" => PinentryOption::DefaultCancel(get_second_part("")),
-            "default-prompt" => PinentryOption::DefaultPrompt(get_second_part("")),
+            "ttytype" => PinentryOption::TtyType(percent::decode(value)?),
+            "ttyname" => PinentryOption::TtYName(percent::decode(value)?),
+            "display" => PinentryOption::Display(percent::decode(value)?),
+            "lc-ctype" => PinentryOption::LccType(percent::decode(value)?),
+            "default-ok" => PinentryOption::DefaultOk(percent::decode(value)?),
+            "default-cancel" => PinentryOption::DefaultCancel(percent::decode(value)?),
+            "default-prompt" => PinentryOption::DefaultPrompt(percent::decode(value)?),
             "allow-external-password-cache" => PinentryOption::AllowExternalPasswordCache,
+            "constraints-enforce" => PinentryOption::ConstraintsEnforce,
+            "constraints-hint-short" => {
+                PinentryOption::ConstraintsHintShort(percent::decode(value)?)
+            }
+            "constraints-hint-long" => PinentryOption::ConstraintsHintLong(percent::decode(value)?),
+            "constraints-error-title" => {
+                PinentryOption::ConstraintsErrorTitle(percent::decode(value)?)
+            }
             _ => PinentryOption::UnknownOption,
-        }
+        };
+        Ok(option)
     }
 }
 
@@ -41,7 +57,7 @@ mod tests {
     fn parses_formatted_passphrase_option() {
         assert_eq!(
             PinentryOption::parse("formatted-passphrase"),
-            PinentryOption::FormattedPassphrase
+            Ok(PinentryOption::FormattedPassphrase)
         );
     }
 
@@ -49,15 +65,15 @@ mod tests {
     fn parses_formatted_passphrase_hint_option() {
         assert_eq!(
             PinentryOption::parse("formatted-passphrase-hint=test"),
-            PinentryOption::FormattedPassphraseHint("test".to_string())
+            Ok(PinentryOption::FormattedPassphraseHint("test".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("formatted-passphrase-hint="),
-            PinentryOption::FormattedPassphraseHint("".to_string())
+            Ok(PinentryOption::FormattedPassphraseHint("".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("formatted-passphrase-hint"),
-            PinentryOption::FormattedPassphraseHint("".to_string())
+            Ok(PinentryOption::FormattedPassphraseHint("".to_string()))
         );
     }
 
@@ -65,15 +81,15 @@ mod tests {
     fn parses_ttytype_option() {
         assert_eq!(
             PinentryOption::parse("ttytype=test"),
-            PinentryOption::TtyType("test".to_string())
+            Ok(PinentryOption::TtyType("test".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("ttytype="),
-            PinentryOption::TtyType("".to_string())
+            Ok(PinentryOption::TtyType("".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("ttytype"),
-            PinentryOption::TtyType("".to_string())
+            Ok(PinentryOption::TtyType("".to_string()))
         );
     }
 
@@ -81,15 +97,27 @@ mod tests {
     fn parses_ttyname_option() {
         assert_eq!(
             PinentryOption::parse("ttyname=test"),
-            PinentryOption::TtYName("test".to_string())
+            Ok(PinentryOption::TtYName("test".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("ttyname="),
-            PinentryOption::TtYName("".to_string())
+            Ok(PinentryOption::TtYName("".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("ttyname"),
-            PinentryOption::TtYName("".to_string())
+            Ok(PinentryOption::TtYName("".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_display_option() {
+        assert_eq!(
+            PinentryOption::parse("display=:0"),
+            Ok(PinentryOption::Display(":0".to_string()))
+        );
+        assert_eq!(
+            PinentryOption::parse("display"),
+            Ok(PinentryOption::Display("".to_string()))
         );
     }
 
@@ -97,15 +125,15 @@ mod tests {
     fn parses_lc_ctype_option() {
         assert_eq!(
             PinentryOption::parse("lc-ctype=test"),
-            PinentryOption::LccType("test".to_string())
+            Ok(PinentryOption::LccType("test".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("lc-ctype="),
-            PinentryOption::LccType("".to_string())
+            Ok(PinentryOption::LccType("".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("lc-ctype"),
-            PinentryOption::LccType("".to_string())
+            Ok(PinentryOption::LccType("".to_string()))
         );
     }
 
@@ -113,15 +141,15 @@ mod tests {
     fn parses_default_ok_option() {
         assert_eq!(
             PinentryOption::parse("default-ok=test"),
-            PinentryOption::DefaultOk("test".to_string())
+            Ok(PinentryOption::DefaultOk("test".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("default-ok="),
-            PinentryOption::DefaultOk("".to_string())
+            Ok(PinentryOption::DefaultOk("".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("default-ok"),
-            PinentryOption::DefaultOk("".to_string())
+            Ok(PinentryOption::DefaultOk("".to_string()))
         );
     }
 
@@ -129,15 +157,15 @@ mod tests {
     fn parses_default_cancel_option() {
         assert_eq!(
             PinentryOption::parse("default-cancel=test"),
-            PinentryOption::DefaultCancel("test".to_string())
+            Ok(PinentryOption::DefaultCancel("test".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("default-cancel="),
-            PinentryOption::DefaultCancel("".to_string())
+            Ok(PinentryOption::DefaultCancel("".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("default-cancel"),
-            PinentryOption::DefaultCancel("".to_string())
+            Ok(PinentryOption::DefaultCancel("".to_string()))
         );
     }
 
@@ -145,15 +173,15 @@ mod tests {
     fn parses_default_prompt_option() {
         assert_eq!(
             PinentryOption::parse("default-prompt=test"),
-            PinentryOption::DefaultPrompt("test".to_string())
+            Ok(PinentryOption::DefaultPrompt("test".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("default-prompt="),
-            PinentryOption::DefaultPrompt("".to_string())
+            Ok(PinentryOption::DefaultPrompt("".to_string()))
         );
         assert_eq!(
             PinentryOption::parse("default-prompt"),
-            PinentryOption::DefaultPrompt("".to_string())
+            Ok(PinentryOption::DefaultPrompt("".to_string()))
         );
     }
 
@@ -161,13 +189,66 @@ mod tests {
     fn parses_allow_external_password_cache_option() {
         assert_eq!(
             PinentryOption::parse("allow-external-password-cache"),
-            PinentryOption::AllowExternalPasswordCache
+            Ok(PinentryOption::AllowExternalPasswordCache)
+        );
+    }
+
+    #[test]
+    fn parses_constraints_enforce_option() {
+        assert_eq!(
+            PinentryOption::parse("constraints-enforce"),
+            Ok(PinentryOption::ConstraintsEnforce)
+        );
+    }
+
+    #[test]
+    fn parses_constraints_text_options() {
+        assert_eq!(
+            PinentryOption::parse("constraints-hint-short=At least 8 characters"),
+            Ok(PinentryOption::ConstraintsHintShort(
+                "At least 8 characters".to_string()
+            ))
+        );
+        assert_eq!(
+            PinentryOption::parse("constraints-hint-long=Use 8 characters%0Aand a digit"),
+            Ok(PinentryOption::ConstraintsHintLong(
+                "Use 8 characters\nand a digit".to_string()
+            ))
+        );
+        assert_eq!(
+            PinentryOption::parse("constraints-error-title=Passphrase Not Allowed"),
+            Ok(PinentryOption::ConstraintsErrorTitle(
+                "Passphrase Not Allowed".to_string()
+            ))
         );
     }
 
     #[test]
     fn returns_unknown_option_for_anything_else() {
-        assert_eq!(PinentryOption::parse("foo"), PinentryOption::UnknownOption);
-        assert_eq!(PinentryOption::parse(""), PinentryOption::UnknownOption);
+        assert_eq!(
+            PinentryOption::parse("foo"),
+            Ok(PinentryOption::UnknownOption)
+        );
+        assert_eq!(PinentryOption::parse(""), Ok(PinentryOption::UnknownOption));
+    }
+
+    #[test]
+    fn keeps_equals_signs_in_option_values() {
+        assert_eq!(
+            PinentryOption::parse("formatted-passphrase-hint=a=b"),
+            Ok(PinentryOption::FormattedPassphraseHint("a=b".to_string()))
+        );
+    }
+
+    #[test]
+    fn decodes_escaped_option_values() {
+        assert_eq!(
+            PinentryOption::parse("default-ok=_Unlock%20key"),
+            Ok(PinentryOption::DefaultOk("_Unlock key".to_string()))
+        );
+        assert_eq!(
+            PinentryOption::parse("default-ok=100%"),
+            Err(AssuanError::InvalidValue)
+        );
     }
 }
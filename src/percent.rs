@@ -0,0 +1,100 @@
+use crate::error::AssuanError;
+
+/// Decodes the `%XX` escapes Assuan uses for bytes that cannot appear
+/// verbatim on a line, such as `%0A` for a newline and `%25` for `%` itself.
+pub fn decode(input: &str) -> Result<String, AssuanError> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+
+        let high = iter.next().and_then(hex_value);
+        let low = iter.next().and_then(hex_value);
+        match (high, low) {
+            (Some(high), Some(low)) => bytes.push(high << 4 | low),
+            _ => return Err(AssuanError::InvalidValue),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| AssuanError::InvalidValue)
+}
+
+/// Escapes the bytes that would break an Assuan line: `%` itself, CR and LF.
+pub fn encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for character in input.chars() {
+        encode_char(character, &mut encoded);
+    }
+    encoded
+}
+
+pub(crate) fn encode_char(character: char, output: &mut String) {
+    output.push_str(escape(character, &mut [0; 4]));
+}
+
+/// Escapes a single character, using `buffer` for characters that are kept.
+pub(crate) fn escape(character: char, buffer: &mut [u8; 4]) -> &str {
+    match character {
+        '%' => "%25",
+        '\r' => "%0D",
+        '\n' => "%0A",
+        _ => character.encode_utf8(buffer),
+    }
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_line_breaking_bytes() {
+        assert_eq!(encode("plain"), "plain");
+        assert_eq!(encode("100%"), "100%25");
+        assert_eq!(encode("a\r\nb"), "a%0D%0Ab");
+    }
+
+    #[test]
+    fn decode_reverses_encode() {
+        let input = "50% of\r\nlines %0A";
+        assert_eq!(decode(&encode(input)), Ok(input.to_string()));
+    }
+
+    #[test]
+    fn leaves_plain_text_unchanged() {
+        assert_eq!(decode("Hello, world!"), Ok("Hello, world!".to_string()));
+        assert_eq!(decode(""), Ok("".to_string()));
+    }
+
+    #[test]
+    fn decodes_escaped_bytes() {
+        assert_eq!(decode("line%0Anext"), Ok("line\nnext".to_string()));
+        assert_eq!(decode("100%25"), Ok("100%".to_string()));
+        assert_eq!(decode("%3c%3E"), Ok("<>".to_string()));
+    }
+
+    #[test]
+    fn decodes_multibyte_characters() {
+        assert_eq!(decode("%C3%A4"), Ok("ä".to_string()));
+    }
+
+    #[test]
+    fn rejects_malformed_escapes() {
+        assert_eq!(decode("100%"), Err(AssuanError::InvalidValue));
+        assert_eq!(decode("%0"), Err(AssuanError::InvalidValue));
+        assert_eq!(decode("%zz"), Err(AssuanError::InvalidValue));
+        assert_eq!(decode("%C3"), Err(AssuanError::InvalidValue));
+    }
+}
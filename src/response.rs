@@ -1,27 +1,76 @@
 use std::fmt::{Display, Formatter};
 
-use crate::error::AssuanError;
+use crate::{error::AssuanError, percent, secret::Secret};
+
+/// Assuan lines may not be longer than this, not counting the trailing newline.
+pub const MAX_LINE_LENGTH: usize = 1000;
 
 #[derive(Debug, PartialEq)]
 pub enum Response {
     Ok(Option<String>),
     Error(AssuanError),
-    Data(String),
+    Data(Secret),
+    Inquire(String, Option<Secret>),
+    /// A status line, the keyword followed by any arguments.
+    Status(String),
 }
 
 impl Display for Response {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        let string = match self {
-            Response::Ok(data) => match data {
-                Some(data) => format!("OK {}", data),
-                None => "OK".to_string(),
-            },
-            Response::Error(error) => error.to_string(),
-            Response::Data(data) => format!("D {}", data),
-        };
+        match self {
+            Response::Ok(Some(data)) => write!(f, "OK {}", data),
+            Response::Ok(None) => write!(f, "OK"),
+            Response::Error(error) => write!(f, "{}", error),
+            Response::Data(data) => write_data_lines(f, data.expose()),
+            Response::Inquire(keyword, Some(parameters)) => {
+                write!(f, "INQUIRE {} ", keyword)?;
+                write_escaped(f, parameters.expose())
+            }
+            Response::Inquire(keyword, None) => write!(f, "INQUIRE {}", keyword),
+            Response::Status(status) => write!(f, "S {}", status),
+        }
+    }
+}
+
+/// Escapes `data` and splits it into as many `D` lines as needed to stay
+/// within the line limit, never splitting an escape sequence. Secrets go
+/// straight to `f` so no copies are left behind.
+fn write_data_lines(f: &mut Formatter, data: &str) -> std::fmt::Result {
+    let mut line_length = 2;
+    f.write_str("D ")?;
 
-        write!(f, "{}", string)
+    for character in data.chars() {
+        let mut buffer = [0; 4];
+        let encoded = percent::escape(character, &mut buffer);
+
+        if line_length + encoded.len() > MAX_LINE_LENGTH {
+            f.write_str("\nD ")?;
+            line_length = 2;
+        }
+        f.write_str(encoded)?;
+        line_length += encoded.len();
     }
+
+    Ok(())
+}
+
+fn write_escaped(f: &mut Formatter, data: &str) -> std::fmt::Result {
+    for character in data.chars() {
+        f.write_str(percent::escape(character, &mut [0; 4]))?;
+    }
+    Ok(())
+}
+
+/// Reassembles the payload of one or more serialized `D` lines.
+pub fn decode_data(lines: &str) -> Result<String, AssuanError> {
+    let mut encoded = String::new();
+    for line in lines.lines() {
+        match line.strip_prefix("D ") {
+            Some(data) => encoded.push_str(data),
+            None => return Err(AssuanError::InvalidValue),
+        }
+    }
+    percent::decode(&encoded)
 }
 
 #[cfg(test)]
@@ -38,12 +87,92 @@ mod tests {
     fn error_response_converts_to_string() {
         assert_eq!(
             Response::Error(AssuanError::UnknownIPCCommand).to_string(),
-            "ERR 536871187 Unknown IPC command <User defined source 1>"
+            "ERR 83886355 Unknown IPC command <Pinentry>"
         );
     }
 
     #[test]
     fn data_response_converts_to_string() {
-        assert_eq!(Response::Data("foo".to_string()).to_string(), "D foo");
+        assert_eq!(Response::Data("foo".into()).to_string(), "D foo");
+    }
+
+    #[test]
+    fn data_response_escapes_percent_and_line_breaks() {
+        assert_eq!(
+            Response::Data("50%\r\nmore".into()).to_string(),
+            "D 50%25%0D%0Amore"
+        );
+    }
+
+    #[test]
+    fn data_response_splits_long_payloads() {
+        let data = "a".repeat(2500);
+        let serialized = Response::Data(data.as_str().into()).to_string();
+        let lines: Vec<&str> = serialized.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert!(lines.iter().all(|line| line.starts_with("D ")));
+        assert_eq!(decode_data(&serialized), Ok(data));
+    }
+
+    #[test]
+    fn data_response_never_splits_escape_sequences() {
+        let data = "%".repeat(400);
+        let serialized = Response::Data(data.as_str().into()).to_string();
+
+        for line in serialized.lines() {
+            assert!(line.len() <= MAX_LINE_LENGTH);
+            assert_eq!((line.len() - 2) % 3, 0);
+        }
+        assert_eq!(decode_data(&serialized), Ok(data));
+    }
+
+    #[test]
+    fn data_response_round_trips() {
+        for data in ["", "pass phrase", "%0A", "line\nbreak\r\n", "ä€𝄞"] {
+            let serialized = Response::Data(data.into()).to_string();
+            assert_eq!(decode_data(&serialized), Ok(data.to_string()));
+        }
+    }
+
+    #[test]
+    fn inquire_response_converts_to_string() {
+        assert_eq!(
+            Response::Inquire("QUALITY".to_string(), None).to_string(),
+            "INQUIRE QUALITY"
+        );
+        assert_eq!(
+            Response::Inquire("QUALITY".to_string(), Some("100% secret\n".into())).to_string(),
+            "INQUIRE QUALITY 100%25 secret%0A"
+        );
+    }
+
+    #[test]
+    fn status_response_converts_to_string() {
+        assert_eq!(
+            Response::Status("PIN_REPEATED".to_string()).to_string(),
+            "S PIN_REPEATED"
+        );
+    }
+
+    #[test]
+    fn debug_output_hides_secrets() {
+        assert_eq!(
+            format!("{:?}", Response::Data("hunter2".into())),
+            "Data(Secret(<redacted>))"
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                Response::Inquire("QUALITY".to_string(), Some("hunter2".into()))
+            ),
+            "Inquire(\"QUALITY\", Some(Secret(<redacted>)))"
+        );
+    }
+
+    #[test]
+    fn decode_data_rejects_other_lines() {
+        assert_eq!(decode_data("OK"), Err(AssuanError::InvalidValue));
     }
 }
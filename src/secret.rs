@@ -0,0 +1,171 @@
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    ptr,
+    sync::atomic::{compiler_fence, Ordering},
+};
+
+/// Holds a passphrase or anything derived from it. The bytes are locked into
+/// memory where the limits allow it, overwritten with zeros when dropped, and
+/// never shown by `Debug` or `Display`. Use [`Secret::expose`] to get at them.
+pub struct Secret {
+    bytes: Vec<u8>,
+}
+
+impl Secret {
+    pub fn new() -> Self {
+        Secret::with_capacity(0)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        let bytes: Vec<u8> = Vec::with_capacity(capacity);
+        // failing to lock, e.g. because of RLIMIT_MEMLOCK, still leaves the
+        // wiping on drop. The pages are never unlocked again, other secrets
+        // may live on them and the lock ends with the process anyway.
+        if bytes.capacity() > 0 {
+            unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.capacity()) };
+        }
+        Secret { bytes }
+    }
+
+    pub fn expose(&self) -> &str {
+        // only ever filled from `&str`s
+        std::str::from_utf8(&self.bytes).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn push_str(&mut self, string: &str) {
+        let length = self.bytes.len() + string.len();
+        if length > self.bytes.capacity() {
+            // growing in place would leave a copy behind, so move into a
+            // new buffer and let the old one be wiped
+            let mut grown = Secret::with_capacity(length.max(2 * self.bytes.capacity()));
+            grown.bytes.extend_from_slice(&self.bytes);
+            *self = grown;
+        }
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    pub fn push(&mut self, character: char) {
+        self.push_str(character.encode_utf8(&mut [0; 4]));
+    }
+}
+
+impl Default for Secret {
+    fn default() -> Self {
+        Secret::new()
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        // nothing is ever written past the length
+        wipe(&mut self.bytes);
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(string: &str) -> Self {
+        let mut secret = Secret::with_capacity(string.len());
+        secret.push_str(string);
+        secret
+    }
+}
+
+impl From<String> for Secret {
+    /// Wipes `string` after copying it.
+    fn from(string: String) -> Self {
+        let secret = Secret::from(string.as_str());
+        wipe(&mut string.into_bytes());
+        secret
+    }
+}
+
+impl Clone for Secret {
+    fn clone(&self) -> Self {
+        Secret::from(self.expose())
+    }
+}
+
+impl PartialEq for Secret {
+    /// Takes the same time for every pair of secrets of the same length.
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes.len() == other.bytes.len()
+            && self
+                .bytes
+                .iter()
+                .zip(&other.bytes)
+                .fold(0, |difference, (a, b)| difference | (a ^ b))
+                == 0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Overwrites `bytes` with zeros in a way the compiler cannot optimize out.
+pub(crate) fn wipe(bytes: &mut [u8]) {
+    for byte in bytes {
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_and_display_are_redacted() {
+        let secret = Secret::from("hunter2");
+
+        assert_eq!(format!("{:?}", secret), "Secret(<redacted>)");
+        assert_eq!(secret.to_string(), "<redacted>");
+        assert_eq!(
+            format!("{:?}", Some(secret.clone())),
+            "Some(Secret(<redacted>))"
+        );
+    }
+
+    #[test]
+    fn grows_without_losing_contents() {
+        let mut secret = Secret::new();
+        for character in "correct horse battery staple".chars() {
+            secret.push(character);
+        }
+        secret.push_str(" ä€");
+
+        assert_eq!(secret.expose(), "correct horse battery staple ä€");
+        assert_eq!(secret.len(), "correct horse battery staple ä€".len());
+    }
+
+    #[test]
+    fn compares_by_contents() {
+        assert_eq!(Secret::from("abc"), Secret::from("abc".to_string()));
+        assert_ne!(Secret::from("abc"), Secret::from("abd"));
+        assert_ne!(Secret::from("abc"), Secret::from("abcd"));
+        assert!(Secret::new().is_empty());
+    }
+
+    #[test]
+    fn wipe_zeroes_every_byte() {
+        let mut bytes = *b"secret";
+        wipe(&mut bytes);
+        assert_eq!(bytes, [0; 6]);
+    }
+}
@@ -0,0 +1,841 @@
+use crate::{
+    backend::{ConfirmOutcome, DialogOptions, MessageOutcome, PinOutcome},
+    client_request::ClientRequest,
+    error::AssuanError,
+    option::PinentryOption,
+    response::{self, Response},
+    secret::Secret,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum DialogRequest {
+    GetPin(DialogOptions),
+    Confirm(DialogOptions),
+    Message(DialogOptions),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DialogOutcome {
+    Pin(PinOutcome),
+    Confirm(ConfirmOutcome),
+    Message(MessageOutcome),
+    /// The dialog could not be shown at all.
+    Failed,
+}
+
+/// What the session wants its driver to do next.
+#[derive(Debug, PartialEq)]
+pub enum Output {
+    /// Send this to the client.
+    Response(Response),
+    /// Show a dialog and hand its outcome to [`Session::finish_dialog`].
+    Dialog(Box<DialogRequest>),
+    /// The client answered a [`Session::inquire_quality`] call.
+    Quality(Option<i32>),
+    /// The client answered a [`Session::inquire_checkpin`] call. Holds why
+    /// the passphrase was rejected, or nothing if it may be used.
+    PinChecked(Option<String>),
+    /// The connection is over, close any open dialog.
+    Closed,
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+    Idle,
+    Dialog,
+    /// Collects the client's answer until it is complete.
+    Inquiring(Inquiry, String),
+    Closed,
+}
+
+#[derive(Debug, PartialEq)]
+enum Inquiry {
+    Quality,
+    CheckPin,
+}
+
+/// The Assuan side of a pinentry without any I/O: bytes from the client go
+/// into [`Session::feed`] and everything that should happen in response comes
+/// back as [`Output`]s.
+pub struct Session {
+    input: Vec<u8>,
+    state: State,
+    flavor: &'static str,
+    pub(crate) timeout: Option<i32>,
+    pub(crate) description: Option<String>,
+    pub(crate) prompt: Option<String>,
+    pub(crate) title: Option<String>,
+    pub(crate) ok_button: Option<String>,
+    pub(crate) cancel_button: Option<String>,
+    pub(crate) not_ok_button: Option<String>,
+    pub(crate) error: Option<String>,
+    pub(crate) repeat: bool,
+    pub(crate) repeat_prompt: Option<String>,
+    pub(crate) repeat_error: Option<String>,
+    pub(crate) repeat_ok: Option<String>,
+    pub(crate) quality_bar: bool,
+    pub(crate) quality_bar_tooltip: Option<String>,
+    pub(crate) generate_pin: bool,
+    pub(crate) generate_pin_tooltip: Option<String>,
+    pub(crate) key_info: Option<String>,
+    pub(crate) formatted_passphrase: bool,
+    pub(crate) formatted_passphrase_hint: Option<String>,
+    pub(crate) constraints_enforce: bool,
+    pub(crate) constraints_hint_short: Option<String>,
+    pub(crate) constraints_hint_long: Option<String>,
+    pub(crate) constraints_error_title: Option<String>,
+    pub(crate) default_ok: Option<String>,
+    pub(crate) default_cancel: Option<String>,
+    pub(crate) default_prompt: Option<String>,
+    pub(crate) tty_name: Option<String>,
+    pub(crate) tty_type: Option<String>,
+    pub(crate) display: Option<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            input: Vec::new(),
+            state: State::Idle,
+            flavor: "gtk4",
+            timeout: None,
+            description: None,
+            prompt: None,
+            title: None,
+            ok_button: None,
+            cancel_button: None,
+            not_ok_button: None,
+            error: None,
+            repeat: false,
+            repeat_prompt: None,
+            repeat_error: None,
+            repeat_ok: None,
+            quality_bar: false,
+            quality_bar_tooltip: None,
+            generate_pin: false,
+            generate_pin_tooltip: None,
+            key_info: None,
+            formatted_passphrase: false,
+            formatted_passphrase_hint: None,
+            constraints_enforce: false,
+            constraints_hint_short: None,
+            constraints_hint_long: None,
+            constraints_error_title: None,
+            default_ok: None,
+            default_cancel: None,
+            default_prompt: None,
+            tty_name: None,
+            tty_type: None,
+            display: None,
+        }
+    }
+
+    pub fn greeting(&self) -> Response {
+        Response::Ok(Some("Pleased to meet you".to_string()))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state == State::Closed
+    }
+
+    /// Sets what `GETINFO flavor` reports, which is up to the backend that
+    /// would show the next dialog.
+    pub fn set_flavor(&mut self, flavor: &'static str) {
+        self.flavor = flavor;
+    }
+
+    /// Takes bytes read from the client. Only complete lines are handled, and
+    /// while a dialog is open everything but inquiry replies stays buffered
+    /// until [`Session::finish_dialog`].
+    pub fn feed(&mut self, input: &[u8]) -> Vec<Output> {
+        if self.is_closed() {
+            return vec![];
+        }
+
+        self.input.extend_from_slice(input);
+        self.process_input()
+    }
+
+    /// Reports how the dialog requested through [`Output::Dialog`] ended.
+    pub fn finish_dialog(&mut self, outcome: DialogOutcome) -> Vec<Output> {
+        if !matches!(self.state, State::Dialog | State::Inquiring(..)) {
+            return vec![];
+        }
+        self.state = State::Idle;
+
+        // the error text only applies to the dialog it was set for
+        self.error = None;
+
+        let responses = match outcome {
+            DialogOutcome::Pin(PinOutcome::Pin(pin)) => pin_responses(pin),
+            DialogOutcome::Pin(PinOutcome::Repeated(pin)) => {
+                // gpg-agent skips its own repeat prompt after this
+                let mut responses = vec![Response::Status("PIN_REPEATED".to_string())];
+                responses.extend(pin_responses(pin));
+                responses
+            }
+            DialogOutcome::Pin(PinOutcome::Canceled) => {
+                vec![Response::Error(AssuanError::Canceled)]
+            }
+            DialogOutcome::Confirm(ConfirmOutcome::Confirmed) => vec![Response::Ok(None)],
+            DialogOutcome::Confirm(ConfirmOutcome::NotConfirmed) => {
+                vec![Response::Error(AssuanError::NotConfirmed)]
+            }
+            DialogOutcome::Confirm(ConfirmOutcome::Canceled) => {
+                vec![Response::Error(AssuanError::Canceled)]
+            }
+            DialogOutcome::Message(MessageOutcome::Dismissed) => vec![Response::Ok(None)],
+            DialogOutcome::Pin(PinOutcome::TimedOut)
+            | DialogOutcome::Confirm(ConfirmOutcome::TimedOut)
+            | DialogOutcome::Message(MessageOutcome::TimedOut) => {
+                vec![Response::Error(AssuanError::Timeout)]
+            }
+            DialogOutcome::Failed => vec![Response::Error(AssuanError::General)],
+        };
+
+        let mut outputs: Vec<Output> = responses.into_iter().map(Output::Response).collect();
+        outputs.extend(self.process_input());
+        outputs
+    }
+
+    /// Asks the client to rate `passphrase` while a GETPIN dialog is open. The
+    /// score arrives as [`Output::Quality`] once the client has answered.
+    pub fn inquire_quality(&mut self, passphrase: &str) -> Vec<Output> {
+        self.inquire(Inquiry::Quality, passphrase)
+    }
+
+    /// Asks the client whether `passphrase` meets its constraints before the
+    /// dialog returns it. The verdict arrives as [`Output::PinChecked`].
+    pub fn inquire_checkpin(&mut self, passphrase: &str) -> Vec<Output> {
+        self.inquire(Inquiry::CheckPin, passphrase)
+    }
+
+    fn inquire(&mut self, inquiry: Inquiry, passphrase: &str) -> Vec<Output> {
+        if self.state != State::Dialog {
+            return vec![];
+        }
+        let keyword = match inquiry {
+            Inquiry::Quality => "QUALITY",
+            Inquiry::CheckPin => "CHECKPIN",
+        };
+        self.state = State::Inquiring(inquiry, String::new());
+
+        let inquiry = Response::Inquire(keyword.to_string(), Some(passphrase.into()));
+        vec![Output::Response(inquiry)]
+    }
+
+    /// The client closed the connection, so all state is wiped.
+    pub fn eof(&mut self) -> Vec<Output> {
+        if self.is_closed() {
+            return vec![];
+        }
+
+        // everything the client set goes, only what the backend set stays
+        *self = Session {
+            state: State::Closed,
+            flavor: self.flavor,
+            ..Session::new()
+        };
+        vec![Output::Closed]
+    }
+
+    fn process_input(&mut self) -> Vec<Output> {
+        let mut outputs = vec![];
+
+        while matches!(self.state, State::Idle | State::Inquiring(..)) {
+            let Some(end) = self.input.iter().position(|byte| *byte == b'\n') else {
+                break;
+            };
+
+            let mut line: Vec<u8> = self.input.drain(..=end).collect();
+            line.pop();
+
+            outputs.extend(self.handle_line(line));
+        }
+
+        outputs
+    }
+
+    fn handle_line(&mut self, line: Vec<u8>) -> Vec<Output> {
+        if let State::Inquiring(inquiry, data) = &mut self.state {
+            let line = String::from_utf8_lossy(&line);
+            let answer = match line.as_ref() {
+                "END" => response::decode_data(data).ok(),
+                "CAN" => None,
+                line if line.starts_with("D ") => {
+                    data.push_str(line);
+                    data.push('\n');
+                    return vec![];
+                }
+                "" => return vec![],
+                line if line.starts_with('#') => return vec![],
+                // like libassuan, anything else ends the inquiry without an
+                // answer
+                _ => None,
+            };
+
+            let output = match inquiry {
+                Inquiry::Quality => {
+                    Output::Quality(answer.and_then(|score| score.trim().parse().ok()))
+                }
+                // an empty answer means there is nothing to complain about, a
+                // client that cannot check lets every passphrase through
+                Inquiry::CheckPin => Output::PinChecked(
+                    answer
+                        .map(|error| error.trim().to_string())
+                        .filter(|error| !error.is_empty()),
+                ),
+            };
+            self.state = State::Dialog;
+            return vec![output];
+        }
+
+        let request = if line.len() > response::MAX_LINE_LENGTH {
+            Err(AssuanError::LineTooLong)
+        } else {
+            String::from_utf8(line)
+                .map_err(|_| AssuanError::InvalidValue)
+                .and_then(|line| ClientRequest::parse(&line))
+        };
+
+        self.handle_request(request)
+    }
+
+    fn handle_request(&mut self, request: Result<ClientRequest, AssuanError>) -> Vec<Output> {
+        let responses = match request {
+            Ok(request) => match request {
+                ClientRequest::Bye => {
+                    self.state = State::Closed;
+                    let goodbye = Response::Ok(Some("Closing connection".to_string()));
+                    return vec![Output::Response(goodbye), Output::Closed];
+                }
+                ClientRequest::Option(option) => self.set_option(option),
+                ClientRequest::GetInfo(subcommand) => self.get_info(&subcommand),
+                ClientRequest::SetTimeout(timeout) => {
+                    self.timeout = Some(timeout);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetDescription(desc) => {
+                    self.description = Some(desc);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetPrompt(prompt) => {
+                    self.prompt = Some(prompt);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetTitle(title) => {
+                    self.title = Some(title);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetOk(ok_button) => {
+                    self.ok_button = Some(ok_button);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetCancel(cancel_button) => {
+                    self.cancel_button = Some(cancel_button);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetNotOk(not_ok_button) => {
+                    self.not_ok_button = Some(not_ok_button);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetError(error) => {
+                    self.error = Some(error);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetRepeat(prompt) => {
+                    self.repeat = true;
+                    self.repeat_prompt = prompt;
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetRepeatError(error) => {
+                    self.repeat_error = Some(error);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetRepeatOk(ok) => {
+                    self.repeat_ok = Some(ok);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetQualityBar => {
+                    self.quality_bar = true;
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetQualityBarTooltip(tooltip) => {
+                    self.quality_bar_tooltip = Some(tooltip);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetGenPin => {
+                    self.generate_pin = true;
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetGenPinTooltip(tooltip) => {
+                    self.generate_pin_tooltip = Some(tooltip);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::SetKeyInfo(key_info) => {
+                    self.key_info = Some(key_info);
+                    vec![Response::Ok(None)]
+                }
+                ClientRequest::GetPin => {
+                    return self.request_dialog(DialogRequest::GetPin(self.dialog_options()))
+                }
+                ClientRequest::Confirm => {
+                    return self.request_dialog(DialogRequest::Confirm(self.dialog_options()))
+                }
+                ClientRequest::ConfirmOneButton | ClientRequest::Message => {
+                    return self.request_dialog(DialogRequest::Message(self.dialog_options()))
+                }
+                ClientRequest::Reset => {
+                    self.reset();
+                    vec![Response::Ok(None)]
+                }
+                _ => vec![Response::Error(AssuanError::NotImplemented)],
+            },
+            Err(error) => vec![Response::Error(error)],
+        };
+
+        responses.into_iter().map(Output::Response).collect()
+    }
+
+    fn request_dialog(&mut self, request: DialogRequest) -> Vec<Output> {
+        self.state = State::Dialog;
+        vec![Output::Dialog(Box::new(request))]
+    }
+
+    fn reset(&mut self) {
+        self.timeout = None;
+        self.description = None;
+        self.prompt = None;
+        self.title = None;
+        self.ok_button = None;
+        self.cancel_button = None;
+        self.not_ok_button = None;
+        self.error = None;
+        self.repeat = false;
+        self.repeat_prompt = None;
+        self.repeat_error = None;
+        self.repeat_ok = None;
+        self.quality_bar = false;
+        self.quality_bar_tooltip = None;
+        self.generate_pin = false;
+        self.generate_pin_tooltip = None;
+        self.key_info = None;
+    }
+
+    fn set_option(&mut self, option: PinentryOption) -> Vec<Response> {
+        match option {
+            PinentryOption::FormattedPassphrase => self.formatted_passphrase = true,
+            PinentryOption::FormattedPassphraseHint(hint) => {
+                self.formatted_passphrase_hint = Some(hint)
+            }
+            PinentryOption::ConstraintsEnforce => self.constraints_enforce = true,
+            PinentryOption::ConstraintsHintShort(hint) => self.constraints_hint_short = Some(hint),
+            PinentryOption::ConstraintsHintLong(hint) => self.constraints_hint_long = Some(hint),
+            PinentryOption::ConstraintsErrorTitle(title) => {
+                self.constraints_error_title = Some(title)
+            }
+            PinentryOption::DefaultOk(label) => self.default_ok = Some(label),
+            PinentryOption::DefaultCancel(label) => self.default_cancel = Some(label),
+            PinentryOption::DefaultPrompt(label) => self.default_prompt = Some(label),
+            PinentryOption::TtYName(tty_name) => self.tty_name = Some(tty_name),
+            PinentryOption::TtyType(tty_type) => self.tty_type = Some(tty_type),
+            PinentryOption::Display(display) => self.display = Some(display),
+            PinentryOption::UnknownOption => {
+                return vec![Response::Error(AssuanError::UnknownOption)];
+            }
+            _ => {}
+        }
+        vec![Response::Ok(None)]
+    }
+
+    fn get_info(&self, subcommand: &str) -> Vec<Response> {
+        let info = match subcommand {
+            "flavor" => self.flavor.to_string(),
+            "version" => env!("CARGO_PKG_VERSION").to_string(),
+            "pid" => std::process::id().to_string(),
+            "ttyinfo" => {
+                let or_dash = |value: &Option<String>| value.as_deref().unwrap_or("-").to_string();
+                format!(
+                    "{} {} {}",
+                    or_dash(&self.tty_name),
+                    or_dash(&self.tty_type),
+                    or_dash(&self.display)
+                )
+            }
+            _ => return vec![Response::Error(AssuanError::IPCParameterError)],
+        };
+
+        vec![Response::Data(info.into()), Response::Ok(None)]
+    }
+
+    pub(crate) fn dialog_options(&self) -> DialogOptions {
+        DialogOptions {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            prompt: self.prompt.clone().or(self.default_prompt.clone()),
+            ok_button: self.ok_button.clone().or(self.default_ok.clone()),
+            cancel_button: self.cancel_button.clone().or(self.default_cancel.clone()),
+            not_ok_button: self.not_ok_button.clone(),
+            error: self.error.clone(),
+            repeat: self.repeat,
+            repeat_prompt: self.repeat_prompt.clone(),
+            repeat_error: self.repeat_error.clone(),
+            repeat_ok: self.repeat_ok.clone(),
+            quality_bar: self.quality_bar,
+            quality_bar_tooltip: self.quality_bar_tooltip.clone(),
+            constraints_enforce: self.constraints_enforce,
+            constraints_hint_short: self.constraints_hint_short.clone(),
+            constraints_hint_long: self.constraints_hint_long.clone(),
+            constraints_error_title: self.constraints_error_title.clone(),
+            generate_pin: self.generate_pin,
+            generate_pin_tooltip: self.generate_pin_tooltip.clone(),
+            key_info: self.key_info.clone(),
+            formatted_passphrase: self.formatted_passphrase,
+            formatted_passphrase_hint: self.formatted_passphrase_hint.clone(),
+            timeout: self
+                .timeout
+                .filter(|timeout| *timeout > 0)
+                .map(|timeout| timeout as u32),
+            tty_name: self.tty_name.clone(),
+            tty_type: self.tty_type.clone(),
+            display: self.display.clone(),
+        }
+    }
+}
+
+/// An empty passphrase is sent as no data at all.
+fn pin_responses(pin: Secret) -> Vec<Response> {
+    if pin.is_empty() {
+        vec![Response::Ok(None)]
+    } else {
+        vec![Response::Data(pin), Response::Ok(None)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn responses(outputs: Vec<Output>) -> Vec<String> {
+        outputs
+            .into_iter()
+            .map(|output| match output {
+                Output::Response(response) => response.to_string(),
+                output => panic!("expected a response, got {:?}", output),
+            })
+            .collect()
+    }
+
+    fn open_dialog(session: &mut Session, command: &[u8]) -> DialogRequest {
+        match session.feed(command).pop() {
+            Some(Output::Dialog(request)) => *request,
+            output => panic!("expected a dialog request, got {:?}", output),
+        }
+    }
+
+    #[test]
+    fn waits_for_complete_lines() {
+        let mut session = Session::new();
+
+        assert_eq!(session.feed(b"SETDESC Hel"), vec![]);
+        assert_eq!(responses(session.feed(b"lo\nNOP")), vec!["OK"]);
+        assert_eq!(session.description, Some("Hello".to_string()));
+    }
+
+    #[test]
+    fn bye_closes_the_session() {
+        let mut session = Session::new();
+
+        assert_eq!(
+            session.feed(b"BYE\nNOP\n"),
+            vec![
+                Output::Response(Response::Ok(Some("Closing connection".to_string()))),
+                Output::Closed
+            ]
+        );
+        assert!(session.is_closed());
+        assert_eq!(session.feed(b"NOP\n"), vec![]);
+    }
+
+    #[test]
+    fn getpin_requests_a_dialog_with_the_current_settings() {
+        let mut session = Session::new();
+        session.feed(b"SETDESC Unlock key\nSETTIMEOUT 5\nSETREPEAT\nSETGENPIN\n");
+        session.feed(b"SETKEYINFO n/1234\n");
+
+        let request = open_dialog(&mut session, b"GETPIN\n");
+        assert_eq!(
+            request,
+            DialogRequest::GetPin(DialogOptions {
+                description: Some("Unlock key".to_string()),
+                repeat: true,
+                generate_pin: true,
+                key_info: Some("n/1234".to_string()),
+                timeout: Some(5),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn formatted_passphrase_options_reach_the_dialog() {
+        let mut session = Session::new();
+        session.feed(b"OPTION formatted-passphrase\n");
+        session.feed(b"OPTION formatted-passphrase-hint=Blanks are not part of it\n");
+
+        let request = open_dialog(&mut session, b"GETPIN\n");
+        assert_eq!(
+            request,
+            DialogRequest::GetPin(DialogOptions {
+                formatted_passphrase: true,
+                formatted_passphrase_hint: Some("Blanks are not part of it".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn constraints_reach_the_dialog() {
+        let mut session = Session::new();
+        session.feed(b"OPTION constraints-enforce\n");
+        session.feed(b"OPTION constraints-hint-short=At least 8 characters\n");
+        session.feed(b"OPTION constraints-hint-long=Use 8 characters and a digit\n");
+        session.feed(b"OPTION constraints-error-title=Passphrase Not Allowed\n");
+
+        let request = open_dialog(&mut session, b"GETPIN\n");
+        assert_eq!(
+            request,
+            DialogRequest::GetPin(DialogOptions {
+                constraints_enforce: true,
+                constraints_hint_short: Some("At least 8 characters".to_string()),
+                constraints_hint_long: Some("Use 8 characters and a digit".to_string()),
+                constraints_error_title: Some("Passphrase Not Allowed".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn confirm_and_message_request_dialogs() {
+        let mut session = Session::new();
+
+        let request = open_dialog(&mut session, b"CONFIRM\n");
+        assert!(matches!(request, DialogRequest::Confirm(_)));
+        session.finish_dialog(DialogOutcome::Confirm(ConfirmOutcome::Confirmed));
+
+        let request = open_dialog(&mut session, b"CONFIRM --one-button\n");
+        assert!(matches!(request, DialogRequest::Message(_)));
+        session.finish_dialog(DialogOutcome::Message(MessageOutcome::Dismissed));
+
+        let request = open_dialog(&mut session, b"MESSAGE\n");
+        assert!(matches!(request, DialogRequest::Message(_)));
+    }
+
+    #[test]
+    fn buffers_input_while_a_dialog_is_open() {
+        let mut session = Session::new();
+        open_dialog(&mut session, b"GETPIN\n");
+
+        assert_eq!(session.feed(b"SETDESC Next\n"), vec![]);
+        assert_eq!(session.description, None);
+
+        let outputs = session.finish_dialog(DialogOutcome::Pin(PinOutcome::Canceled));
+        assert_eq!(
+            responses(outputs),
+            vec!["ERR 83886179 Operation cancelled <Pinentry>", "OK"]
+        );
+        assert_eq!(session.description, Some("Next".to_string()));
+    }
+
+    #[test]
+    fn finished_pin_dialog_sends_the_passphrase() {
+        let mut session = Session::new();
+        session.feed(b"SETERROR Bad passphrase\n");
+        open_dialog(&mut session, b"GETPIN\n");
+
+        let outputs = session.finish_dialog(DialogOutcome::Pin(PinOutcome::Pin("50%".into())));
+        assert_eq!(responses(outputs), vec!["D 50%25", "OK"]);
+        assert_eq!(session.error, None);
+    }
+
+    #[test]
+    fn finished_pin_dialog_without_passphrase_only_sends_ok() {
+        let mut session = Session::new();
+        open_dialog(&mut session, b"GETPIN\n");
+
+        let outputs = session.finish_dialog(DialogOutcome::Pin(PinOutcome::Pin(Secret::new())));
+        assert_eq!(responses(outputs), vec!["OK"]);
+    }
+
+    #[test]
+    fn repeated_pin_is_reported_before_the_passphrase() {
+        let mut session = Session::new();
+        session.feed(b"SETREPEAT Repeat:\nSETREPEATERROR does not match\nSETREPEATOK match\n");
+
+        let request = open_dialog(&mut session, b"GETPIN\n");
+        assert_eq!(
+            request,
+            DialogRequest::GetPin(DialogOptions {
+                repeat: true,
+                repeat_prompt: Some("Repeat:".to_string()),
+                repeat_error: Some("does not match".to_string()),
+                repeat_ok: Some("match".to_string()),
+                ..Default::default()
+            })
+        );
+
+        let outputs =
+            session.finish_dialog(DialogOutcome::Pin(PinOutcome::Repeated("secret".into())));
+        assert_eq!(responses(outputs), vec!["S PIN_REPEATED", "D secret", "OK"]);
+    }
+
+    #[test]
+    fn finished_confirm_dialog_reports_the_choice() {
+        let cases = [
+            (ConfirmOutcome::Confirmed, "OK"),
+            (
+                ConfirmOutcome::NotConfirmed,
+                "ERR 83886194 Not confirmed <Pinentry>",
+            ),
+            (
+                ConfirmOutcome::Canceled,
+                "ERR 83886179 Operation cancelled <Pinentry>",
+            ),
+            (ConfirmOutcome::TimedOut, "ERR 83886142 Timeout <Pinentry>"),
+        ];
+
+        for (outcome, response) in cases {
+            let mut session = Session::new();
+            open_dialog(&mut session, b"CONFIRM\n");
+
+            let outputs = session.finish_dialog(DialogOutcome::Confirm(outcome));
+            assert_eq!(responses(outputs), vec![response]);
+        }
+    }
+
+    #[test]
+    fn timed_out_dialogs_report_a_timeout() {
+        let mut session = Session::new();
+        open_dialog(&mut session, b"MESSAGE\n");
+
+        let outputs = session.finish_dialog(DialogOutcome::Message(MessageOutcome::TimedOut));
+        assert_eq!(responses(outputs), vec!["ERR 83886142 Timeout <Pinentry>"]);
+    }
+
+    #[test]
+    fn failed_dialogs_report_a_general_error() {
+        let mut session = Session::new();
+        open_dialog(&mut session, b"GETPIN\n");
+
+        let outputs = session.finish_dialog(DialogOutcome::Failed);
+        assert_eq!(
+            responses(outputs),
+            vec!["ERR 83886081 General error <Pinentry>"]
+        );
+    }
+
+    #[test]
+    fn finish_dialog_without_dialog_does_nothing() {
+        let mut session = Session::new();
+        assert_eq!(session.finish_dialog(DialogOutcome::Failed), vec![]);
+    }
+
+    #[test]
+    fn quality_inquiry_round_trip() {
+        let mut session = Session::new();
+        open_dialog(&mut session, b"GETPIN\n");
+
+        assert_eq!(
+            responses(session.inquire_quality("100%")),
+            vec!["INQUIRE QUALITY 100%25"]
+        );
+        assert_eq!(session.feed(b"D 4"), vec![]);
+        assert_eq!(session.feed(b"\nD 2\n"), vec![]);
+        assert_eq!(session.feed(b"END\n"), vec![Output::Quality(Some(42))]);
+    }
+
+    #[test]
+    fn canceled_quality_inquiry_has_no_score() {
+        let mut session = Session::new();
+        open_dialog(&mut session, b"GETPIN\n");
+        session.inquire_quality("secret");
+
+        assert_eq!(session.feed(b"D 42\nCAN\n"), vec![Output::Quality(None)]);
+    }
+
+    #[test]
+    fn unexpected_lines_cancel_the_inquiry() {
+        let mut session = Session::new();
+        open_dialog(&mut session, b"GETPIN\n");
+        session.inquire_quality("secret");
+
+        assert_eq!(session.feed(b"# comment\nD 42\n\n"), vec![]);
+        assert_eq!(session.feed(b"NOP\n"), vec![Output::Quality(None)]);
+
+        // the dialog is still open, so a new inquiry can be made
+        assert_eq!(
+            responses(session.inquire_checkpin("secret")),
+            vec!["INQUIRE CHECKPIN secret"]
+        );
+        assert_eq!(session.feed(b"GETPIN\n"), vec![Output::PinChecked(None)]);
+    }
+
+    #[test]
+    fn checkpin_inquiry_reports_why_the_pin_was_rejected() {
+        let mut session = Session::new();
+        open_dialog(&mut session, b"GETPIN\n");
+
+        assert_eq!(
+            responses(session.inquire_checkpin("abc")),
+            vec!["INQUIRE CHECKPIN abc"]
+        );
+        assert_eq!(
+            session.feed(b"D Too short%0AUse at least 8 characters\nEND\n"),
+            vec![Output::PinChecked(Some(
+                "Too short\nUse at least 8 characters".to_string()
+            ))]
+        );
+
+        session.inquire_checkpin("long enough");
+        assert_eq!(session.feed(b"END\n"), vec![Output::PinChecked(None)]);
+    }
+
+    #[test]
+    fn quality_inquiry_needs_an_open_dialog() {
+        let mut session = Session::new();
+        assert_eq!(session.inquire_quality("secret"), vec![]);
+    }
+
+    #[test]
+    fn eof_wipes_state_and_closes() {
+        let mut session = Session::new();
+        session.set_flavor("tty");
+        session.feed(
+            b"SETDESC Hello\nSETKEYINFO n/1234\nOPTION ttyname=/dev/pts/1\n\
+              OPTION display=:0\nOPTION default-ok=Yes\nOPTION constraints-enforce\n\
+              OPTION formatted-passphrase\n",
+        );
+        open_dialog(&mut session, b"GETPIN\n");
+
+        assert_eq!(session.eof(), vec![Output::Closed]);
+        assert_eq!(session.description, None);
+        assert_eq!(session.key_info, None);
+        assert_eq!(session.tty_name, None);
+        assert_eq!(session.display, None);
+        assert_eq!(session.default_ok, None);
+        assert!(!session.constraints_enforce);
+        assert!(!session.formatted_passphrase);
+        assert!(session.input.is_empty());
+        assert_eq!(session.flavor, "tty");
+        assert_eq!(
+            session.finish_dialog(DialogOutcome::Pin(PinOutcome::Canceled)),
+            vec![]
+        );
+    }
+}
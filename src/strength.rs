@@ -0,0 +1,499 @@
+use std::fmt::{self, Display, Formatter};
+
+/// Words that show up in leaked passphrases all the time. Guessers try far
+/// more than these, which is what [`WORD_BITS`] accounts for.
+const COMMON_WORDS: &[&str] = &[
+    "password",
+    "passwort",
+    "letmein",
+    "welcome",
+    "admin",
+    "login",
+    "dragon",
+    "monkey",
+    "master",
+    "shadow",
+    "sunshine",
+    "princess",
+    "football",
+    "baseball",
+    "soccer",
+    "hockey",
+    "superman",
+    "batman",
+    "trustno",
+    "iloveyou",
+    "love",
+    "secret",
+    "hello",
+    "freedom",
+    "whatever",
+    "michael",
+    "jennifer",
+    "jordan",
+    "hunter",
+    "ranger",
+    "buster",
+    "thomas",
+    "robert",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "flower",
+    "cookie",
+    "cheese",
+    "computer",
+    "internet",
+    "access",
+    "starwars",
+    "pokemon",
+    "chocolate",
+    "banana",
+    "orange",
+    "purple",
+    "angel",
+    "tigger",
+    "charlie",
+    "daniel",
+    "ginger",
+    "pepper",
+    "matrix",
+    "killer",
+    "mustang",
+    "harley",
+    "diamond",
+    "silver",
+    "golden",
+    "yellow",
+    "black",
+    "white",
+    "money",
+    "google",
+    "apple",
+    "family",
+    "friend",
+    "house",
+    "pass",
+    "test",
+    "user",
+    "root",
+    "guest",
+    "change",
+    "default",
+    "gnupg",
+    "linux",
+    "windows",
+    "january",
+    "february",
+    "march",
+    "april",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+    "monday",
+    "friday",
+    "sunday",
+];
+/// A guess from a list of about ten thousand words.
+const WORD_BITS: f64 = 13.3;
+
+/// Rows of a US keyboard, unshifted and shifted, and the alphabet.
+const SEQUENCES: &[&str] = &[
+    "`1234567890-=",
+    "~!@#$%^&*()_+",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+    "abcdefghijklmnopqrstuvwxyz",
+];
+
+/// Passphrases with this many bits of entropy get the full score.
+const FULL_SCORE_BITS: f64 = 80.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weakness {
+    Short,
+    CommonWord,
+    KeyboardWalk,
+    Repeats,
+    Date,
+    OneKindOfCharacter,
+}
+
+impl Display for Weakness {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Weakness::Short => "too short",
+            Weakness::CommonWord => "contains a common word",
+            Weakness::KeyboardWalk => "follows the keyboard or alphabet",
+            Weakness::Repeats => "repeats itself",
+            Weakness::Date => "contains a date",
+            Weakness::OneKindOfCharacter => "uses one kind of character",
+        })
+    }
+}
+
+/// How hard a passphrase is to guess, as far as can be told without asking
+/// gpg-agent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    /// From 0 to 100.
+    pub score: i32,
+    pub weaknesses: Vec<Weakness>,
+}
+
+impl Strength {
+    /// What makes the passphrase weak, for showing next to the score.
+    pub fn explanation(&self) -> Option<String> {
+        if self.weaknesses.is_empty() {
+            return None;
+        }
+        let weaknesses: Vec<String> = self.weaknesses.iter().map(Weakness::to_string).collect();
+        Some(weaknesses.join(", "))
+    }
+}
+
+/// Rates `passphrase` by its entropy, counting the parts that follow a
+/// pattern only by how hard the pattern is to guess.
+pub fn estimate(passphrase: &str) -> Strength {
+    let characters: Vec<char> = passphrase.chars().collect();
+    let mut estimate = Estimate {
+        lowered: characters.iter().map(|c| normalize(*c)).collect(),
+        covered: vec![false; characters.len()],
+        characters,
+        bits: 0.0,
+        weaknesses: vec![],
+    };
+
+    estimate.find_runs();
+    estimate.find_repeated_blocks();
+    estimate.find_dates();
+    estimate.find_common_words();
+    estimate.find_keyboard_walks();
+
+    let (pool, kinds) = pool_size(&estimate.characters);
+    let uncovered = estimate.covered.iter().filter(|covered| !**covered).count();
+    let mut bits = estimate.bits;
+    if uncovered > 0 {
+        bits += uncovered as f64 * f64::from(pool).log2();
+    }
+
+    let mut weaknesses = estimate.weaknesses;
+    if !passphrase.is_empty() && estimate.characters.len() < 8 {
+        weaknesses.insert(0, Weakness::Short);
+    }
+    if kinds == 1 && estimate.characters.len() < 16 {
+        weaknesses.push(Weakness::OneKindOfCharacter);
+    }
+    weaknesses.dedup();
+
+    Strength {
+        score: (bits / FULL_SCORE_BITS * 100.0).round().clamp(0.0, 100.0) as i32,
+        weaknesses,
+    }
+}
+
+struct Estimate {
+    characters: Vec<char>,
+    /// Lowercase, with the usual digits and symbols for letters undone.
+    lowered: Vec<char>,
+    /// Characters already counted as part of a pattern.
+    covered: Vec<bool>,
+    /// Entropy of the patterns found so far.
+    bits: f64,
+    weaknesses: Vec<Weakness>,
+}
+
+impl Estimate {
+    fn is_free(&self, start: usize, end: usize) -> bool {
+        !self.covered[start..end].iter().any(|covered| *covered)
+    }
+
+    fn cover(&mut self, start: usize, end: usize, bits: f64, weakness: Weakness) {
+        self.covered[start..end].fill(true);
+        self.bits += bits;
+        if !self.weaknesses.contains(&weakness) {
+            self.weaknesses.push(weakness);
+        }
+    }
+
+    /// `aaaa`: one character and how often it is repeated.
+    fn find_runs(&mut self) {
+        let mut start = 0;
+        while start < self.characters.len() {
+            let end = start
+                + self.characters[start..]
+                    .iter()
+                    .take_while(|c| **c == self.characters[start])
+                    .count();
+            if end - start >= 3 {
+                let (pool, _) = pool_size(&self.characters[start..start + 1]);
+                let bits = f64::from(pool).log2() + ((end - start) as f64).log2();
+                self.cover(start, end, bits, Weakness::Repeats);
+            }
+            start = end;
+        }
+    }
+
+    /// `abcabc`: the second copy only adds where it starts.
+    fn find_repeated_blocks(&mut self) {
+        let length = self.characters.len();
+        for size in (3..=length / 2).rev() {
+            for start in size..=length - size {
+                let end = start + size;
+                if self.is_free(start, end)
+                    && self.characters[start..end] == self.characters[start - size..start]
+                {
+                    self.cover(start, end, (length as f64).log2(), Weakness::Repeats);
+                }
+            }
+        }
+    }
+
+    /// Years from 1900 to 2099 and whole dates, with or without separators.
+    fn find_dates(&mut self) {
+        let is_part = |c: &char| c.is_ascii_digit() || matches!(c, '-' | '/' | '.');
+        let mut start = 0;
+        while start < self.characters.len() {
+            if !self.characters[start].is_ascii_digit() {
+                start += 1;
+                continue;
+            }
+            let mut end = start
+                + self.characters[start..]
+                    .iter()
+                    .take_while(|c| is_part(c))
+                    .count();
+            while !self.characters[end - 1].is_ascii_digit() {
+                end -= 1;
+            }
+
+            let text: String = self.characters[start..end].iter().collect();
+            let bits = match date_kind(&text) {
+                Some(DateKind::Year) => 200f64.log2(),
+                Some(DateKind::Date) => (366.0 * 200f64).log2(),
+                None => 0.0,
+            };
+            if bits > 0.0 && self.is_free(start, end) {
+                self.cover(start, end, bits, Weakness::Date);
+            }
+            start = end;
+        }
+    }
+
+    fn find_common_words(&mut self) {
+        let mut words: Vec<&str> = COMMON_WORDS.to_vec();
+        words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+
+        for word in words {
+            let word: Vec<char> = word.chars().collect();
+            let mut start = 0;
+            while start + word.len() <= self.lowered.len() {
+                let end = start + word.len();
+                if self.lowered[start..end] == word[..] && self.is_free(start, end) {
+                    let capitalized = self.characters[start..end].iter().any(|c| c.is_uppercase());
+                    let bits = WORD_BITS + if capitalized { 1.0 } else { 0.0 };
+                    self.cover(start, end, bits, Weakness::CommonWord);
+                    start = end;
+                } else {
+                    start += 1;
+                }
+            }
+        }
+    }
+
+    /// `qwerty`, `4321` or `defg`: where to start, which way and how far.
+    fn find_keyboard_walks(&mut self) {
+        let keys: usize = SEQUENCES.iter().map(|sequence| sequence.len()).sum();
+        let mut start = 0;
+        while start < self.characters.len() {
+            let end = start + walk_length(&self.characters[start..]);
+            if end - start >= 4 && self.is_free(start, end) {
+                let bits = ((2 * keys) as f64).log2() + ((end - start) as f64).log2();
+                self.cover(start, end, bits, Weakness::KeyboardWalk);
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+    }
+}
+
+/// How many characters from the start of `characters` follow one of the
+/// [`SEQUENCES`], forwards or backwards.
+fn walk_length(characters: &[char]) -> usize {
+    let mut longest = 0;
+    for sequence in SEQUENCES {
+        let position = |c: &char| sequence.find(c.to_ascii_lowercase());
+        let Some(first) = characters.first().and_then(position) else {
+            continue;
+        };
+        for step in [1, -1] {
+            let length = characters
+                .iter()
+                .enumerate()
+                .take_while(|(offset, c)| {
+                    first
+                        .checked_add_signed(step * *offset as isize)
+                        .is_some_and(|expected| position(c) == Some(expected))
+                })
+                .count();
+            longest = longest.max(length);
+        }
+    }
+    longest
+}
+
+enum DateKind {
+    Year,
+    Date,
+}
+
+fn date_kind(text: &str) -> Option<DateKind> {
+    let groups: Vec<&str> = text.split(['-', '/', '.']).collect();
+    let number = |digits: &str| digits.parse::<u32>().ok();
+    let is_year = |digits: &str| digits.len() == 4 && matches!(number(digits), Some(1900..=2099));
+    let is_short_year = |digits: &str| digits.len() == 2 || is_year(digits);
+    let is_day_month = |day: &str, month: &str| {
+        day.len() <= 2
+            && month.len() <= 2
+            && matches!(number(day), Some(1..=31))
+            && matches!(number(month), Some(1..=12))
+    };
+    // day and month can come in either order
+    let is_date = |first: &str, second: &str, year: &str| {
+        is_short_year(year) && (is_day_month(first, second) || is_day_month(second, first))
+    };
+
+    match groups[..] {
+        [digits] => match digits.len() {
+            4 if is_year(digits) => Some(DateKind::Year),
+            6 | 8 => {
+                let year = digits.len() - 4;
+                let date = is_date(&digits[..2], &digits[2..4], &digits[4..])
+                    || is_date(
+                        &digits[year..year + 2],
+                        &digits[year + 2..],
+                        &digits[..year],
+                    );
+                date.then_some(DateKind::Date)
+            }
+            _ => None,
+        },
+        [first, second, third] => (is_date(first, second, third)
+            || (is_year(first) && is_date(second, third, first)))
+        .then_some(DateKind::Date),
+        _ => None,
+    }
+}
+
+/// Undoes capitalization and the usual replacements of letters.
+fn normalize(character: char) -> char {
+    match character {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => character.to_lowercase().next().unwrap_or(character),
+    }
+}
+
+/// The number of characters a guesser would have to try for each position,
+/// and how many kinds of characters that is made of.
+fn pool_size(characters: &[char]) -> (u32, usize) {
+    let kinds = [
+        (26, characters.iter().any(char::is_ascii_lowercase)),
+        (26, characters.iter().any(char::is_ascii_uppercase)),
+        (10, characters.iter().any(char::is_ascii_digit)),
+        (33, characters.iter().any(char::is_ascii_punctuation)),
+        (1, characters.contains(&' ')),
+        (33, characters.iter().any(char::is_ascii_control)),
+        (100, characters.iter().any(|c| !c.is_ascii())),
+    ];
+    let used = kinds.iter().filter(|(_, used)| *used);
+    (used.clone().map(|(size, _)| size).sum(), used.count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_passphrase_scores_nothing() {
+        assert_eq!(
+            estimate(""),
+            Strength {
+                score: 0,
+                weaknesses: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn random_passphrases_score_high() {
+        let strength = estimate("v8#Lq2!xTz@9mR&w");
+        assert_eq!(strength.score, 100);
+        assert_eq!(strength.explanation(), None);
+
+        assert!(estimate("Gk4$xq2").score < estimate("Gk4$xq2Wp9").score);
+    }
+
+    #[test]
+    fn control_characters_count_towards_the_score() {
+        let strength = estimate("Password\t\t\t");
+        assert!((0..=100).contains(&strength.score));
+        assert!(strength.weaknesses.contains(&Weakness::Repeats));
+
+        assert!((1..=100).contains(&estimate("\t").score));
+        assert!((1..=100).contains(&estimate("\t\u{1}\n").score));
+    }
+
+    #[test]
+    fn finds_patterns() {
+        let weaknesses = |passphrase| estimate(passphrase).weaknesses;
+
+        assert_eq!(weaknesses("P4ssw0rd"), vec![Weakness::CommonWord]);
+        assert_eq!(
+            weaknesses("qwertyuiop"),
+            vec![Weakness::KeyboardWalk, Weakness::OneKindOfCharacter]
+        );
+        assert_eq!(
+            weaknesses("xkcdxkcdxkcd"),
+            vec![Weakness::Repeats, Weakness::OneKindOfCharacter]
+        );
+        assert_eq!(weaknesses("Zq!aaaaaaaaa"), vec![Weakness::Repeats]);
+        assert_eq!(weaknesses("Tq7!24.12.1987"), vec![Weakness::Date]);
+        assert_eq!(weaknesses("Tq7!x19870415"), vec![Weakness::Date]);
+        assert_eq!(weaknesses("ab1"), vec![Weakness::Short]);
+    }
+
+    #[test]
+    fn patterns_lower_the_score() {
+        let random = estimate("Xk9$vQ2m").score;
+        for weak in ["Password", "Asdfghjk", "Abcabcab", "Ab@1987!", "Aaaaaaa1"] {
+            assert!(estimate(weak).score < random, "{}", weak);
+        }
+    }
+
+    #[test]
+    fn explains_the_weaknesses() {
+        assert_eq!(
+            estimate("love2012").explanation(),
+            Some("contains a date, contains a common word".to_string())
+        );
+        assert_eq!(
+            estimate("qwer").explanation(),
+            Some(
+                "too short, follows the keyboard or alphabet, uses one kind of character"
+                    .to_string()
+            )
+        );
+    }
+}